@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+
+use crate::gifts::GiftMap;
+use crate::number::Number;
+use crate::{gift_probabilities, merge, pow, GType, GiftFormulas, Linear, ModelProfile, MonsterCount, SlotRanks, WONDERFUL_RANK};
+
+// ------------------- CHAPTER MODELS -----------------
+// Everything that differs between the game modes is part of their chapter model.
+// A new mode needs an implementation of ChapterModel and its line in the chapter list below
+
+pub trait ChapterModel {
+    fn name(&self) -> &'static str;
+
+    /// Color of the chapter in charts that compare the chapters
+    fn color(&self) -> &'static str;
+
+    /// Default formulas of the gift types that have their own probabilities, the other gift types have no formulas
+    fn formulas(&self) -> GiftMap<GiftFormulas>;
+
+    /// Gifts that are placed before the orders start, with the chance that each slot is still empty.
+    /// Generic methods can not be called on a dyn ChapterModel, use Chapter::initial_sequence instead
+    fn initial_sequence<N:Number>(&self, _karma:N, _wonderful_count:usize, profile:&ModelProfile) -> (Vec<N>, SlotRanks<N>) where Self:Sized {
+        profile.rules.empty_slots()
+    }
+
+    /// Tries of every gift type that are used up by the initial sequence
+    fn initial_tries(&self) -> GiftMap<usize> {
+        GiftMap::default()
+    }
+
+    /// The orders in which a monster tries to add gifts after the initial sequence, every order is equally likely
+    fn orders(&self) -> Vec<Vec<GType>>;
+
+    /// Gift types that can show up, from the bottom to the top of the stacked chart
+    fn stack(&self) -> Vec<GType>;
+
+    /// Monsters of a chapter before the user sets them
+    fn monsters(&self) -> MonsterCount {
+        MonsterCount::Fixed(1)
+    }
+
+    /// The monsters are boss fights with a known range, instead of a free amount
+    fn boss_fights(&self) -> bool {
+        false
+    }
+}
+
+/// Defines the Chapter enum with a variant for every chapter model, in the order of the list
+macro_rules! chapter_list {
+    ($($(#[$attribute:meta])* $chapter:ident => $model:ident),* $(,)?) => {
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub(crate) enum Chapter {
+            $($(#[$attribute])* $chapter),*
+        }
+
+        pub(crate) const CHAPTER_COUNT:usize = [$(Chapter::$chapter),*].len();
+
+        impl Chapter {
+            pub const ALL:[Chapter;CHAPTER_COUNT] = [$(Chapter::$chapter),*];
+
+            pub fn model(&self) -> &'static dyn ChapterModel {
+                match self {
+                    $(Chapter::$chapter => &$model),*
+                }
+            }
+
+            pub fn initial_sequence<N:Number>(&self, karma:N, wonderful_count:usize, profile:&ModelProfile) -> (Vec<N>, SlotRanks<N>) {
+                match self {
+                    $(Chapter::$chapter => $model.initial_sequence(karma, wonderful_count, profile)),*
+                }
+            }
+        }
+    };
+}
+
+chapter_list! {
+    #[default]
+    Story => StoryModel,
+    AStory => AlterStoryModel,
+    Towers => TowerModel,
+    SpecialTowers => SpecialTowerModel
+}
+
+// the formulas below are the defaults of the model profile, written as Linear::new(a, b, lo, hi) for clamp(a + b*karma, lo, hi)
+
+const POWER_RANK_UP:[Linear;2] = [
+    Linear::new(0.12, 0.5, 0.12, 0.8),
+    Linear::new(-0.04, 0.4, 0.0, 0.45)
+];
+
+fn bonus_formulas() -> GiftFormulas {
+    GiftFormulas {
+        chosen: vec![
+            Linear::new(0.1, 0.7, 0.25, 0.9),
+            Linear::new(0.0, 0.7, 0.1, 0.9),
+            Linear::constant(0.0)
+        ],
+        rank_up: vec![
+            Linear::new(0.10, 0.7, 0.15, 0.9),
+            Linear::new(-0.06, 0.6, 0.0, 0.5)
+        ]
+    }
+}
+
+/// Quick gifts are the same in story and the towers, except for the chance to rank up to 3 stars
+fn quick_formulas(rank_up3:Linear) -> GiftFormulas {
+    GiftFormulas {
+        chosen: vec![
+            Linear::new(0.1, 0.3, 0.15, 0.5),
+            Linear::new(0.05, 0.3, 0.05, 0.5),
+            Linear::constant(0.0)
+        ],
+        rank_up: vec![Linear::new(0.1, 0.6, 0.15, 0.8), rank_up3]
+    }
+}
+
+// ------------------- STORY -----------------
+
+struct StoryModel;
+
+impl ChapterModel for StoryModel {
+    fn name(&self) -> &'static str {
+        "Story"
+    }
+
+    fn color(&self) -> &'static str {
+        "#9b73d6"
+    }
+
+    fn formulas(&self) -> GiftMap<GiftFormulas> {
+        let mut formulas = GiftMap::default();
+        formulas[GType::Power] = GiftFormulas {
+            chosen: vec![
+                Linear::constant(1.0),
+                Linear::new(0.0, 0.6, 0.1, 0.9),
+                Linear::new(-0.9, 1.0, 0.0, 0.9)
+            ],
+            rank_up: POWER_RANK_UP.to_vec()
+        };
+        formulas[GType::Bonus] = bonus_formulas();
+        formulas[GType::Quick] = quick_formulas(Linear::new(-0.06, 0.5, 0.0, 0.5));
+        formulas[GType::Bounty] = GiftFormulas {
+            chosen: vec![Linear::new(-0.02, 0.2, 0.0, 0.3), Linear::constant(0.0), Linear::constant(0.0)],
+            rank_up: vec![
+                Linear::new(0.1, 0.5, 0.1, 0.8),
+                Linear::new(-0.05, 0.5, 0.0, 0.5)
+            ]
+        };
+        formulas
+    }
+
+    fn orders(&self) -> Vec<Vec<GType>> {
+        vec![
+            vec![GType::Power, GType::Power, GType::Power, GType::Bonus, GType::Bonus, GType::Quick, GType::Quick],
+            vec![GType::Power, GType::Power, GType::Power, GType::Quick, GType::Bonus, GType::Quick, GType::Bonus]]
+    }
+
+    fn stack(&self) -> Vec<GType> {
+        vec![GType::Power, GType::Bonus, GType::Quick]
+    }
+}
+
+// ------------------- ALTER STORY -----------------
+// alter story has between 3 and 6 boss fights, these do not have power gifts
+
+struct AlterStoryModel;
+
+impl ChapterModel for AlterStoryModel {
+    fn name(&self) -> &'static str {
+        "Alter Story"
+    }
+
+    fn color(&self) -> &'static str {
+        "#eabd57"
+    }
+
+    fn formulas(&self) -> GiftMap<GiftFormulas> {
+        let mut formulas = GiftMap::default();
+        formulas[GType::Power] = GiftFormulas { chosen: vec![Linear::constant(0.0);3], rank_up: POWER_RANK_UP.to_vec() };
+        formulas[GType::Bonus] = GiftFormulas {
+            chosen: vec![Linear::new(0.05, 0.3, 0.05, 0.9), Linear::constant(0.0), Linear::constant(0.0)],
+            rank_up: vec![
+                Linear::new(0.1, 0.3, 0.15, 0.9),
+                Linear::new(-0.06, 0.3, 0.0, 0.5)
+            ]
+        };
+        formulas[GType::Quick] = GiftFormulas {
+            chosen: vec![Linear::new(0.05, 0.3, 0.05, 0.9), Linear::constant(0.0), Linear::constant(0.0)],
+            rank_up: vec![
+                Linear::new(0.1, 0.3, 0.15, 0.8),
+                Linear::new(-0.06, 0.3, 0.0, 0.5)
+            ]
+        };
+        //alter story bounties start at 1.0 karma: 0.1 + 0.5*(karma - 1.0) is written as -0.4 + 0.5*karma
+        formulas[GType::Bounty] = GiftFormulas {
+            chosen: vec![Linear::new(-0.4, 0.5, 0.2, 0.8), Linear::constant(0.0), Linear::constant(0.0)],
+            rank_up: vec![
+                Linear::new(-0.3, 0.5, 0.2, 0.8),
+                Linear::new(-0.3, 0.4, 0.1, 0.5)
+            ]
+        };
+        formulas
+    }
+
+    fn orders(&self) -> Vec<Vec<GType>> {
+        vec![vec![GType::Bonus, GType::Quick]]
+    }
+
+    fn stack(&self) -> Vec<GType> {
+        vec![GType::Bonus, GType::Quick]
+    }
+
+    fn monsters(&self) -> MonsterCount {
+        MonsterCount::Distribution([0.25;4])
+    }
+
+    fn boss_fights(&self) -> bool {
+        true
+    }
+}
+
+// ------------------- TOWERS -----------------
+// every tower monster starts with blessings and burdens before the other gifts are tried.
+// Normal and special towers share their formulas
+
+fn tower_formulas() -> GiftMap<GiftFormulas> {
+    let mut formulas = GiftMap::default();
+    formulas[GType::Power] = GiftFormulas {
+        chosen: vec![
+            Linear::constant(1.0),
+            Linear::new(0.0, 0.2, 0.0, 0.5),
+            Linear::constant(0.0)
+        ],
+        rank_up: POWER_RANK_UP.to_vec()
+    };
+    formulas[GType::Bonus] = bonus_formulas();
+    formulas[GType::Quick] = quick_formulas(Linear::new(-0.06, 0.4, 0.0, 0.5));
+    formulas[GType::Bounty] = GiftFormulas {
+        chosen: vec![Linear::new(0.0, 0.3, 0.0, 0.5), Linear::constant(0.0), Linear::constant(0.0)],
+        rank_up: vec![
+            Linear::new(0.1, 0.4, 0.1, 0.8),
+            Linear::new(-0.05, 0.3, 0.0, 0.5)
+        ]
+    };
+    formulas
+}
+
+struct TowerModel;
+
+impl ChapterModel for TowerModel {
+    fn name(&self) -> &'static str {
+        "Towers"
+    }
+
+    fn color(&self) -> &'static str {
+        "#0ebc80"
+    }
+
+    fn formulas(&self) -> GiftMap<GiftFormulas> {
+        tower_formulas()
+    }
+
+    fn initial_sequence<N:Number>(&self, karma:N, _wonderful_count:usize, profile:&ModelProfile) -> (Vec<N>, SlotRanks<N>) {
+        let rules = &profile.rules;
+        let blessing_prob = gift_probabilities(karma, GType::Blessing, Chapter::Towers, profile);
+        let two_gift_chance = blessing_prob.chosen[1].clone();
+
+        //blessings
+        let blessing_ranks = rules.rank_chain(N::one(), &blessing_prob.rank_up);
+
+        //assume only one gift is found, burdens only go up to 3 stars
+        let mut one_gift = rules.no_gifts();
+        {
+            let blessing_rank2 = blessing_ranks[1].clone() - blessing_ranks[2].clone();
+
+            //burdens have a chance to have a rank one lower than the blessing (25% by default)
+            let rank_down = N::from_f64(profile.burden_rank_down);
+            let mut burden_ranks = [N::one(), N::zero(), N::zero()];
+            burden_ranks[1] = blessing_ranks[1].clone() - blessing_rank2 * rank_down.clone();
+            burden_ranks[2] = blessing_ranks[2].clone() * (N::one() - rank_down);
+
+            one_gift[GType::Blessing] = blessing_ranks.clone();
+            one_gift[GType::Burden] = rules.star_ranks(burden_ranks);
+        }
+
+        //assume two gifts are found
+        let mut two_gifts = rules.no_gifts();
+        {
+            // burdens are 3 star it the second gift is a 3 star
+            let mut burden_ranks = [N::one(), N::one(), N::one()];
+            burden_ranks[2] = blessing_ranks[2].clone();
+
+            //There is a chance the burden will be a 3 star if the first blessing is a 3 star but the second isnt (75% by default)
+            burden_ranks[2] += blessing_ranks[2].clone() * (N::one() - blessing_ranks[2].clone()) * N::from_f64(profile.burden_three_star);
+
+            two_gifts[GType::Blessing] = blessing_ranks.iter().map(|r| r.clone() * N::from_f64(2.0)).collect();
+            two_gifts[GType::Burden] = rules.star_ranks(burden_ranks);
+        
+        }
+
+        //the first blessing and the burden always take the first two slots, the second blessing takes the third slot
+        let (mut remaining, mut slots) = rules.empty_slots();
+        slots[0][GType::Blessing] = blessing_ranks.clone();
+        slots[1][GType::Burden] = merge(two_gifts, one_gift, two_gift_chance.clone())[GType::Burden].clone();
+        slots[2][GType::Blessing] = blessing_ranks.iter().map(|r| r.clone() * two_gift_chance.clone()).collect();
+        remaining[..3].clone_from_slice(&[N::zero(), N::zero(), N::one() - two_gift_chance]);
+        (remaining, slots)
+    }
+
+    fn orders(&self) -> Vec<Vec<GType>> {
+        vec![
+            vec![GType::Bonus, GType::Quick],
+            vec![GType::Quick, GType::Bonus]]
+    }
+
+    fn stack(&self) -> Vec<GType> {
+        vec![GType::Blessing, GType::Burden, GType::Bonus, GType::Quick]
+    }
+}
+
+// ------------------- SPECIAL TOWERS -----------------
+
+struct SpecialTowerModel;
+
+impl ChapterModel for SpecialTowerModel {
+    fn name(&self) -> &'static str {
+        "Special Towers"
+    }
+
+    fn color(&self) -> &'static str {
+        "#f03762"
+    }
+
+    fn formulas(&self) -> GiftMap<GiftFormulas> {
+        tower_formulas()
+    }
+
+    fn initial_sequence<N:Number>(&self, karma:N, wonderful_count:usize, profile:&ModelProfile) -> (Vec<N>, SlotRanks<N>) {
+        let rules = &profile.rules;
+        let (mut remaining, mut slots) = rules.empty_slots();
+        let power_prob = gift_probabilities(karma, GType::Power, Chapter::Towers, profile);
+
+        //blessings, the 3 star chance is halved for every wonderful power gift
+        let mut power_ranks = rules.rank_chain(N::one(), &power_prob.rank_up);
+        for rank in power_ranks.iter_mut().skip(WONDERFUL_RANK) {
+            *rank = rank.clone() * pow(N::from_f64(0.5), wonderful_count);
+        }
+
+        //burden split
+        let mut burden_ranks = [N::zero(), N::zero(), N::zero()];
+        burden_ranks[0] = power_ranks[1].clone() * N::from_f64(profile.special_burden_rank2);
+        burden_ranks[1] = power_ranks[2].clone() * N::from_f64(profile.special_burden_rank3);
+        burden_ranks[2] = burden_ranks[1].clone();
+
+        //burden merge
+        burden_ranks[1] += burden_ranks[2].clone();
+        burden_ranks[0] += burden_ranks[1].clone();
+
+        //the power gift takes the first slot and the burden the second
+        let burden_chance = burden_ranks[0].clone();
+        slots[1][GType::Burden] = rules.star_ranks(burden_ranks);
+        slots[0][GType::Power] = power_ranks;
+
+        remaining[..3].clone_from_slice(&[N::zero(), N::one() - burden_chance, N::one()]);
+        (remaining, slots)
+    }
+
+    /// the first power gift counts for blessings
+    fn initial_tries(&self) -> GiftMap<usize> {
+        let mut tries = GiftMap::default();
+        tries[GType::Blessing] = 1;
+        tries
+    }
+
+    fn orders(&self) -> Vec<Vec<GType>> {
+        vec![
+            vec![GType::Blessing, GType::Bonus, GType::Bonus],
+            vec![GType::Bonus, GType::Bonus, GType::Blessing]]
+    }
+
+    fn stack(&self) -> Vec<GType> {
+        vec![GType::Power, GType::Burden, GType::Bonus, GType::Blessing]
+    }
+}
@@ -0,0 +1,283 @@
+use crate::slots::filled_chances;
+use crate::{gift_probabilities, slotless_average_rank, pow, Chapter, GType, ModelProfile, MonsterCount, SlotRanks, WONDERFUL_RANK};
+
+// ------------------- GIFT COUNT DISTRIBUTION -----------------
+// The averages only need the chance that each slot is empty. The distribution of the amount of gifts of one type
+// also needs to know how many of the filled slots hold that type, so both are tracked together as a markov chain.
+// The wonderful power gifts of the order are tracked as well, they halve the chance of the next ones
+
+/// Chance of every amount of gifts, index i is the chance of exactly i gifts
+pub type CountDistribution = Vec<f64>;
+
+/// Chance of every state of a monster, indexed by the amount of filled slots,
+/// then by the amount of wonderful power gifts of the order and then by the amount of tracked gifts
+type SlotStates = Vec<Vec<Vec<f64>>>;
+
+fn no_states(slots:usize) -> SlotStates {
+    vec![vec![vec![0.0; slots + 1]; slots + 1]; slots + 1]
+}
+
+/// Chances of a gift that is added to a slot, indexed by whether it is a wonderful power gift and then by whether it is tracked
+type AddedChances = [[f64;2];2];
+
+pub const LOW_PERCENTILE:f64 = 0.1;
+pub const HIGH_PERCENTILE:f64 = 0.9;
+
+/// Summary of a count distribution
+#[derive(Copy, Clone, Debug)]
+pub struct Spread {
+    pub mean:f64,
+    pub variance:f64,
+    pub low:f64,
+    pub high:f64
+}
+
+impl Spread {
+    pub fn of(distribution:&[f64]) -> Spread {
+        let mean:f64 = distribution.iter().enumerate().map(|(count, chance)| count as f64 * chance).sum();
+        let variance = distribution.iter().enumerate().map(|(count, chance)| (count as f64 - mean).powi(2) * chance).sum();
+        Spread { mean, variance, low: percentile(distribution, LOW_PERCENTILE), high: percentile(distribution, HIGH_PERCENTILE) }
+    }
+}
+
+/// Smallest amount of gifts that is reached with at least the given chance
+fn percentile(distribution:&[f64], chance:f64) -> f64 {
+    let mut total = 0.0;
+    for (count, count_chance) in distribution.iter().enumerate() {
+        total += count_chance;
+        //small margin so rounding errors do not skip a count
+        if total >= chance - 1e-12 {
+            return count as f64;
+        }
+    }
+    (distribution.len() - 1) as f64
+}
+
+/// Adds one gift that is tracked with the given chance
+fn add_bernoulli(distribution:&[f64], chance:f64) -> CountDistribution {
+    let mut result = vec![0.0; distribution.len() + 1];
+    for (count, count_chance) in distribution.iter().enumerate() {
+        result[count] += count_chance * (1.0 - chance);
+        result[count + 1] += count_chance * chance;
+    }
+    result
+}
+
+/// Distribution of the sum of two independent counts
+fn convolve(first:&[f64], second:&[f64]) -> CountDistribution {
+    let mut result = vec![0.0; first.len() + second.len() - 1];
+    for (i, x) in first.iter().enumerate() {
+        for (j, y) in second.iter().enumerate() {
+            result[i + j] += x * y;
+        }
+    }
+    result
+}
+
+/// States after the gifts that are placed before the order starts (the tower blessings and burdens).
+/// Only the average of every slot is known, so the slots are assumed to be independent of each other
+fn initial_states(remaining:&[f64], slots:&SlotRanks, gift_type:GType, rank:usize) -> SlotStates {
+    let slot_count = remaining.len();
+    let mut states = no_states(slot_count);
+    //chance of every amount of tracked gifts in the slots before the current one
+    let mut counts = vec![1.0];
+    //the wonderful gifts of the order start at 0
+    for (filled, (rows, filled_chance)) in states.iter_mut().zip(filled_chances(remaining)).enumerate() {
+        for (state, count_chance) in rows[0].iter_mut().zip(&counts) {
+            *state = filled_chance * count_chance;
+        }
+        if filled < slot_count {
+            let slot_filled = 1.0 - remaining[filled];
+            let tracked = if slot_filled > 0.0 {slots[filled][gift_type][rank] / slot_filled} else {0.0};
+            counts = add_bernoulli(&counts, tracked);
+        }
+    }
+    states
+}
+
+/// Tries to add a gift to the first empty slot, the chances of the added gift depend on the amount of wonderful gifts so far
+fn add_gift(states:&SlotStates, chance:f64, added_chances:impl Fn(usize) -> AddedChances) -> SlotStates {
+    let slot_count = states.len() - 1;
+    let mut next = no_states(slot_count);
+    for (filled, rows) in states.iter().enumerate() {
+        //there can not be more wonderful or tracked gifts than filled slots
+        for (wonderful, counts) in rows.iter().enumerate().take(filled + 1) {
+            let added = added_chances(wonderful);
+            for (count, state) in counts.iter().enumerate().take(filled + 1) {
+                if filled == slot_count {
+                    next[filled][wonderful][count] += state;
+                    continue;
+                }
+                next[filled][wonderful][count] += state * (1.0 - chance);
+                for (is_wonderful, row) in added.iter().enumerate() {
+                    for (is_tracked, added_chance) in row.iter().enumerate() {
+                        next[filled + 1][wonderful + is_wonderful][count + is_tracked] += state * chance * added_chance;
+                    }
+                }
+            }
+        }
+    }
+    next
+}
+
+/// Same steps as try_gift_sequence, while tracking the gifts of one type with at least the given rank
+fn order_states(karma:f64, order:&[GType], wonderful_count:usize, chapter:Chapter, profile:&ModelProfile, gift_type:GType, rank:usize) -> SlotStates {
+    let model = chapter.model();
+    let (remaining, slots) = chapter.initial_sequence(karma, wonderful_count, profile);
+    let mut states = initial_states(&remaining, &slots, gift_type, rank);
+
+    let mut frequency = model.initial_tries();
+
+    for gift_elem in order {
+        if frequency[*gift_elem] >= profile.rules.max_tries {
+            continue;
+        }
+        let prob = gift_probabilities(karma, *gift_elem, chapter, profile);
+        let chance = prob.chosen[frequency[*gift_elem]];
+        let rank_chance = |rank:usize| -> f64 {prob.rank_up[..rank].iter().product()};
+        let is_power = *gift_elem == GType::Power;
+        let added_chances = |order_wonderful:usize| {
+            //rank 3 gift chance is halved for every wonderful power gift
+            let halving = pow(0.5, wonderful_count + order_wonderful);
+            let wonderful = if is_power {rank_chance(WONDERFUL_RANK) * halving} else {0.0};
+            if *gift_elem != gift_type {
+                [[1.0 - wonderful, 0.0], [wonderful, 0.0]]
+            } else if is_power && rank >= WONDERFUL_RANK {
+                //every tracked power gift is wonderful
+                let tracked = rank_chance(rank) * halving;
+                [[1.0 - wonderful, 0.0], [wonderful - tracked, tracked]]
+            } else {
+                //every wonderful power gift is tracked
+                let tracked = rank_chance(rank);
+                [[1.0 - tracked, tracked - wonderful], [0.0, wonderful]]
+            }
+        };
+        states = add_gift(&states, chance, added_chances);
+        frequency[*gift_elem] += 1;
+    }
+    states
+}
+
+/// States of a single monster when every order in the list is equally likely
+fn monster_states(karma:f64, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>], gift_type:GType, rank:usize) -> SlotStates {
+    let factor = 1.0 / orders.len() as f64;
+    let mut result = no_states(profile.rules.slots);
+    for order in orders {
+        let states = order_states(karma, order, wonderful_count, chapter, profile, gift_type, rank);
+        for (result, state) in result.iter_mut().flatten().flatten().zip(states.iter().flatten().flatten()) {
+            *result += factor * state;
+        }
+    }
+    result
+}
+
+/// Distribution of the amount of gifts of one type with at least the given rank (starting at 0) of a single monster
+pub fn monster_distribution(karma:f64, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>], gift_type:GType, rank:usize) -> CountDistribution {
+    //bounty gifts do not take a slot, there is either one or none
+    if !gift_type.info().takes_slot {
+        let chance = slotless_average_rank(karma, gift_type, chapter, profile)[rank];
+        return vec![1.0 - chance, chance];
+    }
+    let states = monster_states(karma, chapter, wonderful_count, profile, orders, gift_type, rank);
+    (0..states.len()).map(|count| states.iter().flatten().map(|counts| counts[count]).sum()).collect()
+}
+
+/// Distribution of the amount of empty slots of a single monster
+pub fn empty_slot_distribution(karma:f64, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>]) -> CountDistribution {
+    let states = monster_states(karma, chapter, wonderful_count, profile, orders, GType::Power, 0);
+    let slot_count = states.len() - 1;
+    (0..=slot_count).map(|empty| states[slot_count - empty].iter().flatten().sum()).collect()
+}
+
+/// Distribution of the total over all monsters of a chapter, when every monster has the given distribution
+pub fn chapter_distribution(monster:&[f64], monsters:&MonsterCount) -> CountDistribution {
+    let weights = monsters.weights();
+    let mut total = vec![1.0];
+    let mut result = Vec::new();
+    for count in 0..=weights.iter().map(|(count, _)| *count).max().unwrap_or(0) {
+        if count > 0 {
+            total = convolve(&total, monster);
+        }
+        if let Some((_, weight)) = weights.iter().find(|(weight_count, _)| *weight_count == count) {
+            result.resize(total.len(), 0.0);
+            for (result, chance) in result.iter_mut().zip(&total) {
+                *result += weight * chance;
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{default_orders, ordered_sequence, GameRules, Linear};
+
+    #[test]
+    fn spread_mean_is_the_average() {
+        let profile = ModelProfile::default();
+        for chapter in Chapter::ALL {
+            let orders = default_orders(chapter);
+            for wonderful_count in [0, 2] {
+                for karma in [0.0, 0.45, 1.0, 1.7] {
+                    let (average, empty_slots) = ordered_sequence(karma, chapter, wonderful_count, &profile, &orders);
+                    for gift_type in GType::ALL {
+                        for rank in 0..profile.rules.ranks {
+                            let mean = Spread::of(&monster_distribution(karma, chapter, wonderful_count, &profile, &orders, gift_type, rank)).mean;
+                            assert!((mean - average[gift_type][rank]).abs() < 1e-12, "{} {} rank {rank} at karma {karma}", chapter.name(), gift_type.name());
+                        }
+                    }
+                    let empty = Spread::of(&empty_slot_distribution(karma, chapter, wonderful_count, &profile, &orders)).mean;
+                    assert!((empty - empty_slots).abs() < 1e-12, "{} empty slots at karma {karma}", chapter.name());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn percentile_is_the_first_count_that_reaches_the_chance() {
+        let distribution = [0.2, 0.5, 0.3];
+        assert_eq!(percentile(&distribution, 0.1), 0.0);
+        assert_eq!(percentile(&distribution, 0.2), 0.0);
+        assert_eq!(percentile(&distribution, 0.5), 1.0);
+        assert_eq!(percentile(&distribution, 0.7), 1.0);
+        assert_eq!(percentile(&distribution, 0.9), 2.0);
+        let spread = Spread::of(&distribution);
+        assert!((spread.mean - 1.1).abs() < 1e-12 && (spread.variance - 0.49).abs() < 1e-12);
+        assert_eq!((spread.low, spread.high), (0.0, 2.0));
+    }
+
+    #[test]
+    fn chapter_distribution_adds_up_the_monsters() {
+        let coin = [0.5, 0.5];
+        assert_eq!(chapter_distribution(&coin, &MonsterCount::Fixed(2)), [0.25, 0.5, 0.25]);
+        //one gift per monster, with 3 or 6 boss fights
+        let boss_fights = MonsterCount::Distribution([0.5, 0.0, 0.0, 0.5]);
+        assert_eq!(chapter_distribution(&[0.0, 1.0], &boss_fights), [0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.5]);
+        let total = chapter_distribution(&[0.7, 0.2, 0.1], &boss_fights);
+        assert!((total.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!((Spread::of(&total).mean - 0.4 * 4.5).abs() < 1e-12);
+    }
+
+    /// 4 star power gifts are halved by the wonderful gifts, not by the 4 star gifts that come before them
+    #[test]
+    fn spread_mean_is_the_average_above_the_wonderful_rank() {
+        let mut profile = ModelProfile::default();
+        profile.set_rules(GameRules { ranks: 4, ..GameRules::default() });
+        for chapter in Chapter::ALL {
+            profile.formulas_mut(GType::Power, chapter).rank_up[WONDERFUL_RANK] = Linear::constant(0.5);
+        }
+        for chapter in Chapter::ALL {
+            let orders = default_orders(chapter);
+            for wonderful_count in [0, 1] {
+                for karma in [0.0, 0.7, 1.6] {
+                    let (average, _) = ordered_sequence(karma, chapter, wonderful_count, &profile, &orders);
+                    for rank in 0..4 {
+                        let mean = Spread::of(&monster_distribution(karma, chapter, wonderful_count, &profile, &orders, GType::Power, rank)).mean;
+                        assert!((mean - average[GType::Power][rank]).abs() < 1e-12, "{} rank {rank} at karma {karma}: {mean} vs {}", chapter.name(), average[GType::Power][rank]);
+                    }
+                }
+            }
+        }
+    }
+}
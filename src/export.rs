@@ -0,0 +1,69 @@
+use std::fmt::Write;
+
+use crate::scenario::{self, Scenario};
+use crate::{ChapterView, GType, PlotProgram, USAGE};
+
+// ------------------- DATA EXPORT -----------------
+
+/// The model data of the chart as csv, with one row per karma value.
+/// Every gift type has the average amount of gifts, and the amount with at least every higher rank.
+/// Empty slots can not be combined into a chance over all monsters, so they are left blank in that view
+pub fn chart_csv(program:&PlotProgram) -> String {
+    let rules = &program.profile.rules;
+    let mut csv = String::from("karma");
+    for gift_type in GType::ALL {
+        let name = gift_type.name();
+        write!(csv, ",{name}").unwrap();
+        for rank in 1..rules.ranks {
+            write!(csv, ",{name} {}", rules.rank_name(rank)).unwrap();
+        }
+    }
+    csv.push_str(",empty slots\n");
+
+    let at_least_one = program.chapter_view == ChapterView::AtLeastOne;
+    let chance = &program.gift_chance;
+    for (i, karma) in program.karma_range.iter().enumerate() {
+        write!(csv, "{karma}").unwrap();
+        for gift_type in GType::ALL {
+            for rank in &chance.of(gift_type)[i] {
+                write!(csv, ",{rank}").unwrap();
+            }
+        }
+        if at_least_one {
+            csv.push_str(",\n");
+        } else {
+            writeln!(csv, ",{}", chance.empty[i]).unwrap();
+        }
+    }
+    csv
+}
+
+pub fn command(args:&[String]) -> Result<(), String> {
+    let mut scenario = Scenario::default();
+    let mut out = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value\n{USAGE}"));
+        match arg.as_str() {
+            "--scenario" => scenario = scenario::find(value()?)?,
+            "--code" => scenario = Scenario::from_code(value()?)?,
+            "--out" => out = Some(value()?.clone()),
+            _ => return Err(format!("Unknown argument {arg}\n{USAGE}"))
+        }
+    }
+
+    let mut program = PlotProgram::default();
+    scenario.apply(&mut program)?;
+    program.recalc();
+    if !program.orders_status.is_empty() {
+        eprintln!("{}", program.orders_status);
+    }
+
+    let csv = chart_csv(&program);
+    match out {
+        Some(path) => std::fs::write(&path, csv).map_err(|e| format!("Could not write {path}: {e}"))?,
+        None => print!("{csv}")
+    }
+    Ok(())
+}
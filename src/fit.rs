@@ -0,0 +1,538 @@
+use crate::likelihood::{self, Group};
+use crate::observations::{self, Observation};
+use crate::{Chapter, GType, Linear, ModelProfile, USAGE};
+
+// ------------------- FITTED PARAMETERS -----------------
+
+const FIELD_NAMES:[&str;4] = ["a", "b", "lo", "hi"];
+
+#[derive(Copy, Clone, Debug)]
+enum Term {
+    Chosen(usize),
+    RankUp(usize)
+}
+
+/// One constant of a clamp(a + b*karma, lo, hi) formula
+#[derive(Copy, Clone, Debug)]
+struct Parameter {
+    gift_type:GType,
+    term:Term,
+    /// index in FIELD_NAMES
+    field:usize
+}
+
+impl Parameter {
+    /// Every constant of the formulas of a gift type, the profile has a formula for every try and rank up the rules allow
+    fn all_of(gift_type:GType, profile:&ModelProfile, chapter:Chapter) -> Vec<Parameter> {
+        let formulas = profile.formulas(gift_type, chapter);
+        let terms = (0..formulas.chosen.len()).map(Term::Chosen).chain((0..formulas.rank_up.len()).map(Term::RankUp));
+        terms
+            .flat_map(|term| (0..4).map(move |field| Parameter { gift_type, term, field }))
+            .collect()
+    }
+
+    fn value_mut<'a>(&self, profile:&'a mut ModelProfile, chapter:Chapter) -> &'a mut f64 {
+        let formulas = profile.formulas_mut(self.gift_type, chapter);
+        let linear = match self.term {
+            Term::Chosen(i) => &mut formulas.chosen[i],
+            Term::RankUp(i) => &mut formulas.rank_up[i]
+        };
+        let [a, b, lo, hi] = linear.parameters_mut();
+        match self.field {
+            0 => a,
+            1 => b,
+            2 => lo,
+            _ => hi
+        }
+    }
+
+    fn name(&self) -> String {
+        let term = match self.term {
+            Term::Chosen(i) => format!("chosen[{i}]"),
+            Term::RankUp(i) => format!("rank_up[{i}]")
+        };
+        format!("{} {term} {}", self.gift_type.name(), FIELD_NAMES[self.field])
+    }
+}
+
+/// A constant of the profile, only constants that influence the likelihood are fitted
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub name:String,
+    pub fitted:bool,
+    pub initial:f64,
+    pub value:f64,
+    /// missing if the likelihood has no curvature in this constant
+    pub std_error:Option<f64>
+}
+
+#[derive(Clone, Debug)]
+pub struct FitResult {
+    pub chapter:Chapter,
+    pub profile:ModelProfile,
+    pub initial_log_likelihood:f64,
+    pub log_likelihood:f64,
+    pub estimates:Vec<Estimate>,
+    /// the last round improved nothing and every simplex shrank to a point
+    pub converged:bool
+}
+
+// ------------------- MAXIMUM LIKELIHOOD -----------------
+
+/// Step used to find out whether the likelihood depends on a parameter at all
+const SCREEN_STEP:f64 = 0.01;
+/// Step of the numerical second derivatives
+const HESSIAN_STEP:f64 = 1e-4;
+const MAX_ITERATIONS:usize = 4000;
+/// A simplex has converged when its values and its points are this close together
+const VALUE_TOLERANCE:f64 = 1e-10;
+const SIZE_TOLERANCE:f64 = 1e-7;
+/// Restarts and rounds stop when the negative log-likelihood improves less than this
+const IMPROVEMENT_TOLERANCE:f64 = 1e-9;
+const MAX_RESTARTS:usize = 20;
+const MAX_ROUNDS:usize = 50;
+
+/// Estimates the formula constants of the given gift types in a chapter by maximum likelihood.
+/// Only constants that change the likelihood of the observations are fitted, the others keep their value from the profile.
+///
+/// The constants are fitted one gift type at a time. Every gift type is first fitted on its own from the profile,
+/// the best of those is the start of rounds that fit every gift type in turn until a round improves nothing.
+/// So fitting more gift types is never worse than fitting one of them alone
+pub fn fit(observations:&[Observation], chapter:Chapter, gift_types:&[GType], profile:&ModelProfile) -> FitResult {
+    let groups = likelihood::group_observations(observations, chapter, &profile.rules);
+    let all_parameters:Vec<Parameter> = gift_types.iter().flat_map(|gift_type| Parameter::all_of(*gift_type, profile, chapter)).collect();
+    let initial:Vec<f64> = {
+        let mut profile = profile.clone();
+        all_parameters.iter().map(|p| *p.value_mut(&mut profile, chapter)).collect()
+    };
+    let initial_log_likelihood = likelihood::log_likelihood(&groups, chapter, profile);
+
+    let free = sensitive_parameters(&groups, chapter, profile, &all_parameters);
+    let free_parameters:Vec<Parameter> = free.iter().map(|i| all_parameters[*i]).collect();
+    let start:Vec<f64> = free.iter().map(|i| initial[*i]).collect();
+
+    let negative_log_likelihood = |values:&[f64]| -> f64 {
+        let candidate = with_values(profile, chapter, &free_parameters, values);
+        if !is_valid(&candidate, chapter, gift_types) {
+            return f64::INFINITY;
+        }
+        -likelihood::log_likelihood(&groups, chapter, &candidate)
+    };
+
+    let blocks:Vec<Vec<usize>> = gift_types.iter()
+        .map(|gift_type| (0..free_parameters.len()).filter(|i| free_parameters[*i].gift_type == *gift_type).collect())
+        .filter(|block:&Vec<usize>| !block.is_empty())
+        .collect();
+
+    let start = Minimum { value: negative_log_likelihood(&start), point: start, converged: true };
+    let mut best = start.clone();
+    for block in &blocks {
+        let alone = fit_block(&negative_log_likelihood, &start, block);
+        if alone.value < best.value {
+            best = alone;
+        }
+    }
+    let mut converged = false;
+    for _ in 0..MAX_ROUNDS {
+        let round_start = best.value;
+        let mut round_converged = true;
+        for block in &blocks {
+            best = fit_block(&negative_log_likelihood, &best, block);
+            round_converged &= best.converged;
+        }
+        if round_start - best.value < IMPROVEMENT_TOLERANCE {
+            converged = round_converged;
+            break;
+        }
+    }
+    let best = best.point;
+    let fitted = with_values(profile, chapter, &free_parameters, &best);
+    let std_errors = standard_errors(&negative_log_likelihood, &best);
+
+    let estimates = all_parameters.iter().enumerate().map(|(i, parameter)| {
+        let free_index = free.iter().position(|f| *f == i);
+        Estimate {
+            name: parameter.name(),
+            fitted: free_index.is_some(),
+            initial: initial[i],
+            value: free_index.map_or(initial[i], |f| best[f]),
+            std_error: free_index.and_then(|f| std_errors[f])
+        }
+    }).collect();
+
+    FitResult {
+        chapter,
+        log_likelihood: likelihood::log_likelihood(&groups, chapter, &fitted),
+        profile: fitted,
+        initial_log_likelihood,
+        estimates,
+        converged
+    }
+}
+
+/// Point with the lowest value found so far
+#[derive(Clone, Debug)]
+struct Minimum {
+    point:Vec<f64>,
+    value:f64,
+    converged:bool
+}
+
+/// Minimizes f over the parameters of one block while the others keep their value.
+/// Nelder-Mead can get stuck on the kinks of the clamps, so it restarts from the best point until that stops improving
+fn fit_block(f:&dyn Fn(&[f64]) -> f64, start:&Minimum, block:&[usize]) -> Minimum {
+    let with_block = |values:&[f64]| {
+        let mut point = start.point.clone();
+        for (i, value) in block.iter().zip(values) {
+            point[*i] = *value;
+        }
+        point
+    };
+    let block_f = |values:&[f64]| f(&with_block(values));
+    let mut best = Minimum { point: block.iter().map(|i| start.point[*i]).collect(), value: start.value, converged: false };
+    let mut step = 0.05;
+    for _ in 0..MAX_RESTARTS {
+        let found = nelder_mead(&block_f, &best.point, step);
+        let settled = best.value - found.value < IMPROVEMENT_TOLERANCE;
+        let converged = found.converged;
+        if found.value <= best.value {
+            best = found;
+        }
+        if settled {
+            return Minimum { point: with_block(&best.point), value: best.value, converged };
+        }
+        step = f64::max(step / 2.0, 0.01);
+    }
+    Minimum { point: with_block(&best.point), value: best.value, converged: false }
+}
+
+fn with_values(profile:&ModelProfile, chapter:Chapter, parameters:&[Parameter], values:&[f64]) -> ModelProfile {
+    let mut profile = profile.clone();
+    for (parameter, value) in parameters.iter().zip(values) {
+        *parameter.value_mut(&mut profile, chapter) = *value;
+    }
+    profile
+}
+
+/// Formulas have to stay probabilities: 0 <= lo <= hi <= 1
+fn is_valid(profile:&ModelProfile, chapter:Chapter, gift_types:&[GType]) -> bool {
+    gift_types.iter().all(|gift_type| {
+        let formulas = profile.formulas(*gift_type, chapter);
+        formulas.chosen.iter().chain(&formulas.rank_up)
+            .all(Linear::is_probability)
+    })
+}
+
+/// Indices of the parameters that change the likelihood when they are moved a little
+fn sensitive_parameters(groups:&[Group], chapter:Chapter, profile:&ModelProfile, parameters:&[Parameter]) -> Vec<usize> {
+    let base = likelihood::log_likelihood(groups, chapter, profile);
+    (0..parameters.len()).filter(|i| {
+        [-SCREEN_STEP, SCREEN_STEP].iter().any(|step| {
+            let mut moved = profile.clone();
+            *parameters[*i].value_mut(&mut moved, chapter) += step;
+            let changed = likelihood::log_likelihood(groups, chapter, &moved);
+            changed.is_finite() && (changed - base).abs() > 1e-9
+        })
+    }).collect()
+}
+
+/// Minimizes f with the Nelder-Mead simplex method, starting from a simplex with the given step along every axis.
+/// It has converged when the values and the points of the simplex are all close together
+fn nelder_mead(f:&dyn Fn(&[f64]) -> f64, start:&[f64], step:f64) -> Minimum {
+    let n = start.len();
+    if n == 0 {
+        return Minimum { point: Vec::new(), value: f(start), converged: true };
+    }
+    let mut simplex:Vec<(Vec<f64>, f64)> = (0..=n).map(|i| {
+        let mut point = start.to_vec();
+        if i > 0 {
+            point[i - 1] += step;
+        }
+        let value = f(&point);
+        (point, value)
+    }).collect();
+
+    let mut converged = false;
+    for _ in 0..MAX_ITERATIONS {
+        simplex.sort_by(|x, y| x.1.total_cmp(&y.1));
+        let spread = (simplex[n].1 - simplex[0].1).abs();
+        let size = simplex[1..].iter()
+            .flat_map(|(point, _)| point.iter().zip(&simplex[0].0).map(|(x, best)| (x - best).abs()))
+            .fold(0.0, f64::max);
+        if spread < VALUE_TOLERANCE && size < SIZE_TOLERANCE {
+            converged = true;
+            break;
+        }
+
+        let centroid:Vec<f64> = (0..n).map(|d| simplex[..n].iter().map(|(p, _)| p[d]).sum::<f64>() / n as f64).collect();
+        let towards = |factor:f64| -> Vec<f64> {
+            centroid.iter().zip(&simplex[n].0).map(|(c, w)| c + factor * (w - c)).collect()
+        };
+
+        let reflected = towards(-1.0);
+        let reflected_value = f(&reflected);
+        if reflected_value < simplex[0].1 {
+            let expanded = towards(-2.0);
+            let expanded_value = f(&expanded);
+            simplex[n] = if expanded_value < reflected_value {(expanded, expanded_value)} else {(reflected, reflected_value)};
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            let contracted = towards(0.5);
+            let contracted_value = f(&contracted);
+            if contracted_value < simplex[n].1 {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                //shrink everything towards the best point
+                let best = simplex[0].0.clone();
+                for (point, value) in simplex.iter_mut().skip(1) {
+                    for (x, b) in point.iter_mut().zip(&best) {
+                        *x = b + 0.5 * (*x - b);
+                    }
+                    *value = f(point);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|x, y| x.1.total_cmp(&y.1));
+    let (point, value) = simplex.swap_remove(0);
+    Minimum { point, value, converged }
+}
+
+/// Standard errors from the inverse of the numerical hessian of the negative log-likelihood.
+/// Parameters without curvature (such as the bounds of a clamp that is never reached) have no standard error
+fn standard_errors(f:&dyn Fn(&[f64]) -> f64, best:&[f64]) -> Vec<Option<f64>> {
+    let n = best.len();
+    let h = HESSIAN_STEP;
+    let at = |moves:&[(usize, f64)]| {
+        let mut point = best.to_vec();
+        for (i, step) in moves {
+            point[*i] += step;
+        }
+        f(&point)
+    };
+    let center = f(best);
+
+    let hessian:Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| {
+        if i == j {
+            (at(&[(i, h)]) - 2.0 * center + at(&[(i, -h)])) / (h * h)
+        } else {
+            (at(&[(i, h), (j, h)]) - at(&[(i, h), (j, -h)]) - at(&[(i, -h), (j, h)]) + at(&[(i, -h), (j, -h)])) / (4.0 * h * h)
+        }
+    }).collect()).collect();
+
+    let curved:Vec<usize> = (0..n).filter(|i| hessian[*i][*i].is_finite() && hessian[*i][*i] > 1e-6).collect();
+    let reduced:Vec<Vec<f64>> = curved.iter().map(|i| curved.iter().map(|j| hessian[*i][*j]).collect()).collect();
+    let mut errors = vec![None; n];
+    if let Some(inverse) = invert(reduced) {
+        for (k, i) in curved.iter().enumerate() {
+            if inverse[k][k] > 0.0 {
+                errors[*i] = Some(inverse[k][k].sqrt());
+            }
+        }
+    }
+    errors
+}
+
+/// Gauss-Jordan elimination with partial pivoting, None if the matrix is singular
+fn invert(mut matrix:Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut inverse:Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j {1.0} else {0.0}).collect()).collect();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|x, y| matrix[*x][column].abs().total_cmp(&matrix[*y][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+
+        let scale = matrix[column][column];
+        for j in 0..n {
+            matrix[column][j] /= scale;
+            inverse[column][j] /= scale;
+        }
+        for row in 0..n {
+            if row != column {
+                let factor = matrix[row][column];
+                for j in 0..n {
+                    matrix[row][j] -= factor * matrix[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+    }
+    Some(inverse)
+}
+
+// ------------------- COMMAND LINE -----------------
+
+/// `karma_plot fit`, fits the formulas of every chapter in the gift log and prints the estimates
+pub fn command(args:&[String]) -> Result<(), String> {
+    let mut log_path = None;
+    let mut chapters = Vec::new();
+    let mut gift_types = Vec::new();
+    let mut profile = ModelProfile::default();
+    let mut out_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value\n{USAGE}"));
+        match arg.as_str() {
+            "--chapter" => {
+                let name = value()?;
+                chapters.push(Chapter::from_name(name).ok_or_else(|| format!("unknown chapter '{name}'"))?);
+            },
+            "--gift" => {
+                let name = value()?;
+                let gift_type = GType::from_name(name).filter(GType::has_formulas).ok_or_else(|| {
+                    let names:Vec<&str> = GType::ALL.iter().filter(|gift_type| gift_type.has_formulas()).map(|gift_type| gift_type.name()).collect();
+                    format!("'{name}' has no formulas, choose from {}", names.join(", "))
+                })?;
+                gift_types.push(gift_type);
+            },
+            "--profile" => profile = ModelProfile::load(value()?)?,
+            "--out" => out_path = Some(value()?.clone()),
+            _ if log_path.is_none() && !arg.starts_with("--") => log_path = Some(arg.clone()),
+            _ => return Err(format!("Unknown argument {arg}\n{USAGE}"))
+        }
+    }
+
+    let log_path = log_path.ok_or_else(|| format!("Missing gift log\n{USAGE}"))?;
+    let observations = observations::load_observations(&log_path)?;
+    if chapters.is_empty() {
+        chapters = Chapter::ALL.into_iter().filter(|c| observations.iter().any(|o| o.chapter == *c)).collect();
+    }
+    if gift_types.is_empty() {
+        gift_types = GType::ALL.into_iter().filter(GType::has_formulas).collect();
+    }
+
+    for chapter in chapters {
+        let result = fit(&observations, chapter, &gift_types, &profile);
+        print_result(&result);
+        profile = result.profile;
+    }
+
+    if let Some(out_path) = out_path {
+        profile.save(&out_path)?;
+        println!("fitted profile written to {out_path}");
+    }
+    Ok(())
+}
+
+fn print_result(result:&FitResult) {
+    println!("{}: log-likelihood {:.3} -> {:.3}", result.chapter.name(), result.initial_log_likelihood, result.log_likelihood);
+    if !result.converged {
+        println!("warning: the fit has not converged, the estimates may not be the maximum likelihood");
+    }
+    println!("{:<24} {:>9} {:>9} {:>9}", "constant", "initial", "fitted", "std err");
+    for estimate in &result.estimates {
+        let std_error = match (estimate.fitted, estimate.std_error) {
+            (false, _) => "fixed".to_string(),
+            (true, None) => "n/a".to_string(),
+            (true, Some(error)) => format!("{error:.4}")
+        };
+        println!("{:<24} {:>9.4} {:>9.4} {:>9}", estimate.name, estimate.initial, estimate.value, std_error);
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observations::{ObservedGift, XorShift};
+    use crate::{chapter_sequence, GiftFormulas};
+
+    #[test]
+    fn nelder_mead_finds_the_minimum_of_a_quadratic() {
+        let f = |x:&[f64]| (x[0] - 1.0).powi(2) + 2.0 * (x[1] + 0.5).powi(2) + 0.5 * (x[0] - 1.0) * (x[1] + 0.5) + 3.0;
+        let minimum = nelder_mead(&f, &[0.0, 0.0], 0.1);
+        assert!(minimum.converged);
+        assert!((minimum.point[0] - 1.0).abs() < 1e-6 && (minimum.point[1] + 0.5).abs() < 1e-6, "{:?}", minimum.point);
+        assert!((minimum.value - 3.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn invert_two_by_two() {
+        let inverse = invert(vec![vec![4.0, 7.0], vec![2.0, 6.0]]).unwrap();
+        let expected = [[0.6, -0.7], [-0.2, 0.4]];
+        for (row, expected_row) in inverse.iter().zip(expected) {
+            for (value, expected_value) in row.iter().zip(expected_row) {
+                assert!((value - expected_value).abs() < 1e-12, "{inverse:?}");
+            }
+        }
+        assert!(invert(vec![vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+    }
+
+    /// Index of the cell that a uniform number from 0 to 1 falls in
+    fn draw(rng:&mut XorShift, chances:&[f64]) -> usize {
+        let mut left = rng.next_u64() as f64 / u64::MAX as f64;
+        for (cell, chance) in chances.iter().enumerate() {
+            left -= chance;
+            if left < 0.0 {
+                return cell;
+            }
+        }
+        chances.len() - 1
+    }
+
+    /// Monsters of a chapter with the slots and bounties drawn from the model of the profile
+    fn simulated_log(chapter:Chapter, profile:&ModelProfile, monsters:usize) -> Vec<Observation> {
+        let rules = &profile.rules;
+        let mut rng = XorShift::new(7);
+        let mut observations = Vec::new();
+        for karma in (0..=10).map(|i| i as f64 * 0.2) {
+            let model = chapter_sequence(karma, chapter, 0, profile);
+            let slot_chances:Vec<f64> = likelihood::slot_cells(&model, rules).iter().map(|cell| cell / rules.slots as f64).collect();
+            let slotless_chances = likelihood::slotless_cells(&model);
+            for _ in 0..monsters {
+                let mut gifts = Vec::new();
+                for _ in 0..rules.slots {
+                    let cell = draw(&mut rng, &slot_chances);
+                    //the last cell is an empty slot
+                    if let Some(gift_type) = GType::slot_types().nth(cell / rules.ranks) {
+                        gifts.push(ObservedGift { gift_type, rank: cell % rules.ranks + 1 });
+                    }
+                }
+                for (gift_type, chances) in GType::slotless().zip(slotless_chances.chunks(rules.ranks + 1)) {
+                    let cell = draw(&mut rng, chances);
+                    if cell < rules.ranks {
+                        gifts.push(ObservedGift { gift_type, rank: cell + 1 });
+                    }
+                }
+                observations.push(Observation { run: String::new(), karma, chapter, gifts, wonderful: 0 });
+            }
+        }
+        observations
+    }
+
+    #[test]
+    fn fit_recovers_the_constants_of_the_simulated_profile() {
+        //no clamp is reached, so only a and b change the likelihood
+        let bounty = |chosen:(f64, f64), rank_ups:[(f64, f64);2]| GiftFormulas {
+            chosen: vec![Linear::new(chosen.0, chosen.1, 0.0, 1.0); 3],
+            rank_up: rank_ups.iter().map(|(a, b)| Linear::new(*a, *b, 0.0, 1.0)).collect()
+        };
+        let mut truth = ModelProfile::default();
+        *truth.formulas_mut(GType::Bounty, Chapter::Story) = bounty((0.1, 0.2), [(0.2, 0.2), (0.1, 0.15)]);
+        let mut start = truth.clone();
+        *start.formulas_mut(GType::Bounty, Chapter::Story) = bounty((0.2, 0.1), [(0.3, 0.1), (0.2, 0.05)]);
+
+        let observations = simulated_log(Chapter::Story, &truth, 2000);
+        let result = fit(&observations, Chapter::Story, &[GType::Bounty], &start);
+        assert!(result.converged);
+        assert_eq!(result.estimates.iter().filter(|estimate| estimate.fitted).count(), 6);
+        assert!(result.log_likelihood > result.initial_log_likelihood);
+
+        let mut truth_values = truth.clone();
+        let parameters = Parameter::all_of(GType::Bounty, &truth, Chapter::Story);
+        for (estimate, parameter) in result.estimates.iter().zip(&parameters) {
+            let true_value = *parameter.value_mut(&mut truth_values, Chapter::Story);
+            if estimate.fitted {
+                let std_error = estimate.std_error.expect("every fitted constant has curvature");
+                assert!((estimate.value - true_value).abs() < 4.0 * std_error, "{}: {} instead of {true_value}", estimate.name, estimate.value);
+            } else {
+                assert_eq!(estimate.value, estimate.initial, "{}", estimate.name);
+            }
+        }
+    }
+}
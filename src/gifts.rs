@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::ops::{Index, IndexMut};
+
+use crate::GType;
+
+// ------------------- GIFT REGISTRY -----------------
+// Everything that is known about a gift type. A new gift type needs a GType variant, its entry in GIFTS
+// and a higher GIFT_COUNT. Results and the formulas of the profile are stored in a GiftMap so they grow with the registry.
+// A gift type with its own formulas is never chosen until a chapter model gives it default formulas,
+// and it is only tried once it is part of the orders (and the stack) of a chapter
+
+pub const GIFT_COUNT:usize = 6;
+
+/// Where the chances of a gift type to be chosen and to rank up come from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProbabilitySource {
+    /// formulas of its own in the model profile
+    Own,
+    /// the chance to be chosen of one gift type and the chance to rank up of another
+    Borrowed { chosen:GType, rank_up:GType },
+    /// only placed by the initial sequence of a chapter, so it can not be tried in an order
+    Placed
+}
+
+pub struct GiftInfo {
+    pub gift_type:GType,
+    pub name:&'static str,
+    /// colors of the 1, 2 and 3 star gifts
+    pub colors:[&'static str;3],
+    pub probabilities:ProbabilitySource,
+    /// false for gifts that are offered next to the 3 gift slots, like bounties
+    pub takes_slot:bool
+}
+
+/// All gift types, in the order of the GType variants
+pub const GIFTS:[GiftInfo;GIFT_COUNT] = [
+    GiftInfo {
+        gift_type: GType::Power,
+        name: "power",
+        colors: ["#2a2a59","#44338e","#9b73d6"],
+        probabilities: ProbabilitySource::Own,
+        takes_slot: true
+    },
+    GiftInfo {
+        gift_type: GType::Bonus,
+        name: "bonus",
+        colors: ["#f0892b","#eabd57","#e3dc66"],
+        probabilities: ProbabilitySource::Own,
+        takes_slot: true
+    },
+    GiftInfo {
+        gift_type: GType::Quick,
+        name: "quick",
+        colors: ["#069c80","#0ebc80","#71e380"],
+        probabilities: ProbabilitySource::Own,
+        takes_slot: true
+    },
+    //blessings are chosen like power gifts and rank up like quick gifts
+    GiftInfo {
+        gift_type: GType::Blessing,
+        name: "blessing",
+        colors: ["#8534ae","#cf2be2","#f648e3"],
+        probabilities: ProbabilitySource::Borrowed { chosen: GType::Power, rank_up: GType::Quick },
+        takes_slot: true
+    },
+    GiftInfo {
+        gift_type: GType::Burden,
+        name: "burden",
+        colors: ["#8f3937","#c14552","#f03762"],
+        probabilities: ProbabilitySource::Placed,
+        takes_slot: true
+    },
+    GiftInfo {
+        gift_type: GType::Bounty,
+        name: "bounty",
+        colors: ["#527ea8","#66b5d5","#77e1ec"],
+        probabilities: ProbabilitySource::Own,
+        takes_slot: false
+    }
+];
+
+/// Amount of gift types that take a slot
+pub const SLOT_GIFT_COUNT:usize = {
+    let mut count = 0;
+    let mut i = 0;
+    while i < GIFT_COUNT {
+        if GIFTS[i].takes_slot {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+};
+
+// ------------------- GIFT MAP -----------------
+
+/// A value for every gift type, stored in the order of the registry
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GiftMap<T>([T;GIFT_COUNT]);
+
+impl<T> GiftMap<T> {
+    pub fn from_fn(f:impl FnMut(GType) -> T) -> GiftMap<T> {
+        GiftMap(GType::ALL.map(f))
+    }
+
+    pub fn map<U>(self, f:impl FnMut(T) -> U) -> GiftMap<U> {
+        GiftMap(self.0.map(f))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GType, &T)> {
+        GType::ALL.into_iter().zip(self.0.iter())
+    }
+
+    pub fn values(&self) -> std::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+
+    pub fn values_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.0.iter_mut()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GType, &mut T)> {
+        GType::ALL.into_iter().zip(self.0.iter_mut())
+    }
+}
+
+impl<T> Index<GType> for GiftMap<T> {
+    type Output = T;
+    fn index(&self, gift_type:GType) -> &T {
+        &self.0[gift_type as usize]
+    }
+}
+
+impl<T> IndexMut<GType> for GiftMap<T> {
+    fn index_mut(&mut self, gift_type:GType) -> &mut T {
+        &mut self.0[gift_type as usize]
+    }
+}
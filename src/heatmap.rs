@@ -0,0 +1,45 @@
+use eframe::egui::Color32;
+
+use crate::GType;
+
+// ------------------- WONDERFUL COUNT HEATMAP -----------------
+
+/// Highest amount of wonderful power gifts that can be set
+pub const MAX_WONDERFUL_COUNT:usize = 4;
+
+/// Color scale from the lowest to the highest value of the heatmap
+const HEAT_COLORS:[&str;5] = ["#440154", "#3b528b", "#21918c", "#5ec962", "#fde725"];
+
+#[derive(Clone)]
+pub struct HeatmapSettings {
+    pub show:bool,
+    pub gift_type:GType,
+    /// gifts with at least this rank (starting at 0) are counted
+    pub rank:usize,
+    /// values of the chosen gift, indexed by wonderful count and then by karma
+    pub values:Vec<Vec<f64>>
+}
+
+impl Default for HeatmapSettings {
+    fn default() -> Self {
+        HeatmapSettings { show: false, gift_type: GType::Power, rank: 2, values: Vec::new() }
+    }
+}
+
+impl HeatmapSettings {
+    /// Lowest and highest value over all cells
+    pub fn range(&self) -> (f64, f64) {
+        self.values.iter().flatten().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| (min.min(*value), max.max(*value)))
+    }
+}
+
+/// Color of a value in the heatmap, the part is 0 for the lowest value and 1 for the highest
+pub fn heat_color(part:f64) -> Color32 {
+    let position = part.clamp(0.0, 1.0) * (HEAT_COLORS.len() - 1) as f64;
+    let index = (position.floor() as usize).min(HEAT_COLORS.len() - 2);
+    let from = Color32::from_hex(HEAT_COLORS[index]).unwrap();
+    let to = Color32::from_hex(HEAT_COLORS[index + 1]).unwrap();
+    let t = position - index as f64;
+    let mix = |from:u8, to:u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+    Color32::from_rgb(mix(from.r(), to.r()), mix(from.g(), to.g()), mix(from.b(), to.b()))
+}
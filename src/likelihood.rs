@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use crate::observations::Observation;
+use crate::gifts::SLOT_GIFT_COUNT;
+use crate::{chapter_sequence, exact_ranks, Chapter, GType, GameRules, GiftRanks, ModelProfile};
+
+// ------------------- OUTCOME CELLS -----------------
+// Every gift slot of a monster is treated as one trial that ends up as one of the slot gift types
+// with an exact rank, or empty. Every gift type that does not take a slot (the bounty) is a separate trial
+// that is either one of the ranks or missing.
+
+/// Every gift type that takes a slot with every rank, plus an empty slot
+pub fn slot_cell_count(rules:&GameRules) -> usize {
+    SLOT_GIFT_COUNT * rules.ranks + 1
+}
+
+/// Every rank of every gift type that does not take a slot, plus none of that gift type
+pub fn slotless_cell_count(rules:&GameRules) -> usize {
+    GType::slotless().count() * (rules.ranks + 1)
+}
+
+/// Average amount of slots per monster that end up in each slot cell
+pub fn slot_cells(gifts:&GiftRanks, rules:&GameRules) -> Vec<f64> {
+    let mut cells:Vec<f64> = GType::slot_types().flat_map(|gift_type| exact_ranks(&gifts[gift_type])).collect();
+    let filled:f64 = cells.iter().sum();
+    cells.push(f64::max(rules.slots as f64 - filled, 0.0));
+    cells
+}
+
+/// Average amount of gifts per monster that end up in each slotless cell
+pub fn slotless_cells(gifts:&GiftRanks) -> Vec<f64> {
+    GType::slotless().flat_map(|gift_type| {
+        let mut cells = exact_ranks(&gifts[gift_type]);
+        cells.push(f64::max(1.0 - gifts[gift_type][0], 0.0));
+        cells
+    }).collect()
+}
+
+pub fn slot_cell_name(cell:usize, rules:&GameRules) -> String {
+    if cell == slot_cell_count(rules) - 1 {
+        "empty slot".to_string()
+    } else {
+        format!("{} {} star", GType::slot_types().nth(cell / rules.ranks).unwrap().name(), cell % rules.ranks + 1)
+    }
+}
+
+pub fn slotless_cell_name(cell:usize, rules:&GameRules) -> String {
+    let gift_type = GType::slotless().nth(cell / (rules.ranks + 1)).unwrap();
+    match cell % (rules.ranks + 1) {
+        rank if rank == rules.ranks => format!("no {}", gift_type.name()),
+        rank => format!("{} {} star", gift_type.name(), rank + 1)
+    }
+}
+
+// ------------------- GROUPED OBSERVATIONS -----------------
+
+/// All observed monsters of one chapter with the same karma and wonderful count.
+/// The model only has to be evaluated once for every group
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub karma:f64,
+    pub wonderful:usize,
+    pub monsters:f64,
+    /// summed slot and slotless cells of all monsters
+    pub slots:Vec<f64>,
+    pub slotless:Vec<f64>
+}
+
+pub fn group_observations<'a>(observations:impl IntoIterator<Item = &'a Observation>, chapter:Chapter, rules:&GameRules) -> Vec<Group> {
+    let mut groups:BTreeMap<(u64, usize), Group> = BTreeMap::new();
+    for observation in observations.into_iter().filter(|o| o.chapter == chapter) {
+        let counts = observation.gift_counts(rules);
+        let group = groups.entry((observation.karma.to_bits(), observation.wonderful)).or_insert_with(|| Group {
+            karma: observation.karma,
+            wonderful: observation.wonderful,
+            monsters: 0.0,
+            slots: vec![0.0; slot_cell_count(rules)],
+            slotless: vec![0.0; slotless_cell_count(rules)]
+        });
+        group.monsters += 1.0;
+        for (total, count) in group.slots.iter_mut().zip(slot_cells(&counts, rules)) {
+            *total += count;
+        }
+        for (total, count) in group.slotless.iter_mut().zip(slotless_cells(&counts)) {
+            *total += count;
+        }
+    }
+    groups.into_values().collect()
+}
+
+// ------------------- LIKELIHOOD -----------------
+
+/// Chances below this are treated as this, so an observation the model calls impossible is very unlikely instead of -inf
+pub const MIN_CHANCE:f64 = 1e-12;
+
+/// Multinomial log-likelihood (without the constant term) of the observed cells given the average cells of the model
+pub fn cell_log_likelihood(observed:&[f64], expected:&[f64], trials:f64) -> f64 {
+    observed.iter().zip(expected)
+        .filter(|(observed, _)| **observed > 0.0)
+        .map(|(observed, expected)| observed * f64::max(expected / trials, MIN_CHANCE).ln())
+        .sum()
+}
+
+/// Log-likelihood of a single group of observations
+pub fn group_log_likelihood(group:&Group, chapter:Chapter, profile:&ModelProfile) -> f64 {
+    let model = chapter_sequence(group.karma, chapter, group.wonderful, profile);
+    cell_log_likelihood(&group.slots, &slot_cells(&model, &profile.rules), profile.rules.slots as f64)
+        + cell_log_likelihood(&group.slotless, &slotless_cells(&model), 1.0)
+}
+
+pub fn log_likelihood(groups:&[Group], chapter:Chapter, profile:&ModelProfile) -> f64 {
+    groups.iter().map(|group| group_log_likelihood(group, chapter, profile)).sum()
+}
@@ -2,589 +2,2447 @@ use eframe::egui;
 use egui_plot as plt;
 
 use egui::ecolor::Color32;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+mod chapters;
+mod distribution;
+mod export;
+mod fit;
+mod gifts;
+mod heatmap;
+mod likelihood;
+mod number;
+mod observations;
+mod report;
+mod scenario;
+mod slots;
+mod table;
+mod utility;
+mod verify;
+use chapters::{Chapter, CHAPTER_COUNT};
+use distribution::{Spread, HIGH_PERCENTILE, LOW_PERCENTILE};
+use gifts::{GiftInfo, GiftMap, ProbabilitySource, GIFTS, GIFT_COUNT};
+use heatmap::{HeatmapSettings, MAX_WONDERFUL_COUNT};
+use number::Number;
+use observations::{EmpiricalBin, IntervalMethod, Observation};
+use scenario::{Scenario, ScenarioPanel};
+use slots::SlotState;
+use table::DataTable;
+use utility::{Optimum, UtilitySettings, FLAT_FRACTION};
+
+const USAGE:&str = "usage: karma_plot [--observations <gift log>] [--profile <profile>] [--scenario <name or file>] [--code <scenario code>]
+       karma_plot fit <gift log> [--chapter <chapter>] [--gift <type>]... [--profile <profile>] [--out <profile>]
+       karma_plot report <gift log> [--profile <profile>] [--bin-width <karma>] [--residuals <count>] [--format markdown|json]
+       karma_plot export [--scenario <name or file>] [--code <scenario code>] [--out <csv file>]
+       karma_plot verify [--scenario <name or file>] [--code <scenario code>]";
+
+/// Files given on the command line when starting the plot window
+#[derive(Default)]
+struct StartupFiles {
+    observations:Option<String>,
+    profile:Option<String>,
+    scenario:Option<String>,
+    code:Option<String>
+}
+
 fn main() {
+    let args:Vec<String> = std::env::args().skip(1).collect();
+    let command_result = match args.first().map(|a| a.as_str()) {
+        Some("fit") => Some(fit::command(&args[1..])),
+        Some("report") => Some(report::command(&args[1..])),
+        Some("export") => Some(export::command(&args[1..])),
+        Some("verify") => Some(verify::command(&args[1..])),
+        _ => None
+    };
+    if let Some(result) = command_result {
+        if let Err(error) = result {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut files = StartupFiles::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--observations" => files.observations = args.next(),
+            "--profile" => files.profile = args.next(),
+            "--scenario" => files.scenario = args.next(),
+            "--code" => files.code = args.next(),
+            _ => {
+                eprintln!("Unknown argument {arg}\n{USAGE}");
+                std::process::exit(1);
+            }
+        }
+    }
+
     let mut native_options = eframe::NativeOptions::default();
     native_options.viewport = native_options.viewport.with_inner_size([350.0,450.0]);
-    let _ = eframe::run_native("Karma Plot", native_options, Box::new(|cc| Box::new(PlotProgram::new(cc))))
+    eframe::run_native("Karma Plot", native_options, Box::new(|cc| Box::new(PlotProgram::new(cc, files))))
                 .expect("Unexpected error");
     
 }
 
 #[derive(Default,Clone)]
 struct PlotProgram { 
-    karma_range:Vec<i32>,
+    karma_range:Vec<f64>,
     domain_settings:DomainSettings,
     gift_chance:GiftChance,
     wonderful_count:usize,
     chapter:Chapter,
-    bounty_view:bool
+    view:GiftView,
+    monsters:ChapterMonsters,
+    chapter_view:ChapterView,
+    /// show the average amount of empty slots on top of the gifts
+    show_empty:bool,
+    /// show error bars for the spread of the gifts with at least this rank (starting at 0)
+    show_spread:bool,
+    spread_rank:usize,
+    /// show the gifts at the cursor karma as a donut chart
+    show_donut:bool,
+    profile:ModelProfile,
+    /// fingerprint of the profile at the last recalculation, the key of the cached sequences
+    profile_key:u64,
+    charts:ChartCache,
+    sequence_cache:HashMap<SequenceKey, (GiftRanks, f64)>,
+    observations:Vec<Observation>,
+    observation_settings:ObservationSettings,
+    empirical:Vec<EmpiricalBin>,
+    profile_path:String,
+    /// result of the last attempt to load or save a profile
+    profile_status:String,
+    /// orders that replace the default orders of the chapter
+    custom_orders:Option<Vec<Vec<GType>>>,
+    orders_text:String,
+    orders_status:String,
+    scenarios:ScenarioPanel,
+    table:DataTable,
+    utility:UtilitySettings,
+    cursor:KarmaCursor,
+    heatmap:HeatmapSettings,
+    /// gifts of every chapter, only calculated for the dashboard view
+    dashboard:Vec<(Chapter, GiftChance)>
 }
 
+/// Karma value that is inspected in the breakdown panel
 #[derive(Default,Clone)]
-struct DomainSettings {
-    min:i32,
-    max:i32,
-    step:i32
+struct KarmaCursor {
+    show:bool,
+    karma:f64,
+    values:CursorValues
 }
 
+/// Everything the breakdown panel and the donut show at the cursor karma,
+/// only rebuilt when the cursor moves or the settings change
 #[derive(Default,Clone)]
-struct GiftChance {
-    power:Vec<AverageRank>,
-    bonus:Vec<AverageRank>,
-    quick:Vec<AverageRank>,
-    blessing:Vec<AverageRank>,
-    burden:Vec<AverageRank>,
-    bounty:Vec<AverageRank>
+struct CursorValues {
+    gift_types:Vec<GType>,
+    gift_ranks:GiftRanks,
+    empty_slots:f64,
+    probabilities:Vec<(GType, Probabilities)>,
+    donut:Vec<DonutSlice>
 }
 
-type AverageRank = [f64;3];
-
-struct Probabilities {
-    chosen:[f64;3],
-    rank_up:[f64;2]
+#[derive(Default,Clone)]
+struct ObservationSettings {
+    path:String,
+    /// result of the last attempt to load a gift log
+    status:String,
+    bin_width:f64,
+    interval:IntervalMethod,
+    show:bool
 }
 
-#[derive(Copy,Clone,Debug)]
-enum GType {
-    Power,
-    Bonus,
-    Quick,
-    Blessing,
-    Burden,
-    Bounty
+#[derive(Clone,Serialize,Deserialize)]
+struct DomainSettings {
+    min:f64,
+    max:f64,
+    step:f64,
+    /// highest karma that can be chosen as max
+    limit:f64,
+    unit:KarmaUnit
 }
 
-#[derive(Copy, Clone, Debug, Default,PartialEq)]
-enum Chapter {
+/// Unit of the karma axis, the settings themselves are always in raw karma
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum KarmaUnit {
     #[default]
-    Story,
-    AStory,
-    Towers,
-    SpecialTowers
+    Raw,
+    Percent
 }
 
-const POWER_COLORS:[&str;3] = ["#2a2a59","#44338e","#9b73d6"];
-const BONUS_COLORS:[&str;3] = ["#f0892b","#eabd57","#e3dc66"];
-const QUICK_COLORS:[&str;3] = ["#069c80","#0ebc80","#71e380"];
-const BLESS_COLORS:[&str;3] = ["#8534ae","#cf2be2","#f648e3"];
-const BURDN_COLORS:[&str;3] = ["#8f3937","#c14552","#f03762"];
-const BOUNT_COLORS:[&str;3] = ["#527ea8","#66b5d5","#77e1ec"];
+const MAX_KARMA_POINTS:f64 = 20_000.0;
+const MIN_KARMA_STEP:f64 = 0.0001;
+const MAX_KARMA_LIMIT:f64 = 100.0;
 
-
-fn gift_color(gift_type:GType, rank:usize)-> Color32 {
-    let hex_color = match gift_type {
-        GType::Power => POWER_COLORS[rank],
-        GType::Bonus => BONUS_COLORS[rank],
-        GType::Quick => QUICK_COLORS[rank],
-        GType::Blessing => BLESS_COLORS[rank],
-        GType::Burden => BURDN_COLORS[rank],
-        GType::Bounty => BOUNT_COLORS[rank]
-    };
-    egui::ecolor::Color32::from_hex(hex_color).unwrap()
+impl Default for DomainSettings {
+    fn default() -> Self {
+        DomainSettings { min: 0.0, max: 2.0, step: 0.01, limit: 3.0, unit: KarmaUnit::Raw }
+    }
 }
 
-// ------------------- GIFT SPECIFIC FUNCTIONS -----------------
+impl DomainSettings {
+    /// Every karma value from min up to max with steps of step
+    fn karma_range(&self) -> Vec<f64> {
+        //small margin so a max that is a multiple of step is not lost to rounding errors
+        let count = ((self.max - self.min) / self.step + 1e-9).floor() as usize;
+        (0..=count).map(|i| self.min + i as f64 * self.step).collect()
+    }
 
-fn gift_probabilities(karma:f64, gift_type:GType, chapter:Chapter) -> Probabilities {
-    match gift_type {
-        GType::Power => power_probabilities(karma, chapter),
-        GType::Bonus => bonus_probabilities(karma, chapter),
-        GType::Quick => quick_probabilities(karma, chapter),
-        GType::Bounty => bounty_probabilities(karma, chapter),
-        GType::Blessing => Probabilities { 
-            chosen: power_probabilities(karma,chapter).chosen, 
-            rank_up: quick_probabilities(karma,chapter).rank_up },
-        GType::Burden => panic!("Burdens do not have any probabilities")
-    }
-}
-
-
-fn power_probabilities(karma: f64,chapter:Chapter) -> Probabilities {
-    Probabilities {
-        chosen: match chapter {
-            Chapter::Story => [
-                1.0,
-                clamp(karma * 0.6, 0.1, 0.9),
-                clamp(karma - 0.9, 0.0, 0.9)
-            ],
-            Chapter::AStory => [0.0;3],
-            Chapter::Towers | Chapter::SpecialTowers => [
-                1.0,
-                clamp(0.2*karma, 0.0, 0.5),
-                0.0
-            ]
-        },
-        rank_up: [
-            clamp(0.12 + karma*0.5,0.12,0.8),
-            clamp(-0.04 + karma*0.4, 0.0, 0.45)
-        ]
-    }
-} 
-
-fn bonus_probabilities(karma: f64, chapter:Chapter) -> Probabilities {
-    match chapter {
-        Chapter::Story | Chapter::Towers | Chapter::SpecialTowers => Probabilities { 
-            chosen: [
-                clamp(0.1 + 0.7*karma, 0.25, 0.9),
-                clamp(0.7*karma, 0.1, 0.9),
-                0.0
-            ],
-            rank_up: [
-                clamp(0.10 + karma*0.7, 0.15, 0.9),
-                clamp(-0.06 + karma*0.6, 0.0, 0.5)
-            ]},
-        Chapter::AStory => Probabilities { 
-            chosen: [clamp(0.05 + 0.3*karma, 0.05, 0.9),0.0,0.0], 
-            rank_up: [
-                clamp(0.1 + 0.3 *karma, 0.15, 0.9),
-                clamp(-0.06 + 0.3*karma, 0.0, 0.5)
-            ] }
+    /// Settings that can not be chosen in the ui are rejected, a tiny or negative step would never end
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0 <= self.min && self.min <= self.max && self.max <= self.limit && self.limit <= MAX_KARMA_LIMIT) {
+            return Err(format!("karma should be 0 <= min <= max <= limit <= {MAX_KARMA_LIMIT}, not min {} max {} limit {}", self.min, self.max, self.limit));
+        }
+        if self.step < MIN_KARMA_STEP {
+            return Err(format!("karma step {} is smaller than {MIN_KARMA_STEP}", self.step));
+        }
+        if (self.max - self.min) / self.step > MAX_KARMA_POINTS + 1e-9 {
+            return Err(format!("karma step {} gives more than {MAX_KARMA_POINTS} karma values", self.step));
+        }
+        Ok(())
     }
-    
-    
 }
 
-fn quick_probabilities(karma: f64, chapter:Chapter) -> Probabilities {
-    match chapter {
-        Chapter::Story | Chapter::Towers | Chapter::SpecialTowers => Probabilities { 
-            chosen: [
-                clamp(0.1 + 0.3*karma, 0.15, 0.5),
-                clamp(0.05 + 0.3*karma, 0.05, 0.5),
-                0.0
-            ],
-            rank_up: [
-                clamp(0.1 + 0.6*karma, 0.15, 0.8),
-                match chapter {
-                    Chapter::Story  => clamp(-0.06 + 0.5*karma, 0.0, 0.5),
-                    _               => clamp(-0.06 + 0.4*karma, 0.0, 0.5)
-                }
-            ]},
-        Chapter::AStory => Probabilities { 
-            chosen: [clamp(0.05 + 0.3*karma, 0.05, 0.9),0.0,0.0], 
-            rank_up: [
-                clamp(0.1 + 0.3*karma, 0.15, 0.8),
-                clamp(-0.06 + 0.3*karma, 0.0, 0.5)
-            ] }
+impl KarmaUnit {
+    fn scale(&self) -> f64 {
+        match self {
+            KarmaUnit::Raw => 1.0,
+            KarmaUnit::Percent => 100.0
+        }
     }
-    
-    
+
+    fn axis_label(&self) -> &'static str {
+        match self {
+            KarmaUnit::Raw => "karma",
+            KarmaUnit::Percent => "karma (%)"
+        }
+    }
+}
+
+/// The gifts of every type for every karma value
+#[derive(Default,Clone)]
+struct GiftChance {
+    ranks:GiftMap<Vec<AverageRank>>,
+    /// average amount of slots without a gift
+    empty:Vec<f64>
 }
 
-fn bounty_probabilities(karma:f64, chapter:Chapter) -> Probabilities {
-    match chapter {
-        Chapter::Story => Probabilities { 
-            chosen: [clamp(-0.02 + 0.2*karma, 0.0, 0.3), 0.0, 0.0], 
-            rank_up: [
-                clamp(0.1 + 0.5*karma, 0.1, 0.8),
-                clamp(-0.05 + 0.5*karma, 0.0, 0.5)
-            ] },
-        Chapter::AStory => Probabilities { 
-            chosen: [clamp(0.1 + 0.5*(karma - 1.0), 0.2, 0.8),0.0, 0.0], 
-            rank_up: [
-                clamp(0.2 + 0.5*(karma - 1.0), 0.2, 0.8),
-                clamp(0.1 + 0.4*(karma - 1.0), 0.1, 0.5)
-            ] },
-        Chapter::Towers | Chapter::SpecialTowers => Probabilities { 
-            chosen: [clamp(0.3*karma, 0.0, 0.5),0.0, 0.0], 
-            rank_up: [
-                clamp(0.1 + 0.4*karma, 0.1, 0.8),
-                clamp(-0.05 + 0.3*karma, 0.0, 0.5)
-            ] },
+impl GiftChance {
+    fn of(&self, gift_type:GType) -> &Vec<AverageRank> {
+        &self.ranks[gift_type]
     }
 }
 
+/// Average amount of gifts with at least every rank, index 0 counts all gifts and the last index only the highest rank
+type AverageRank<N = f64> = Vec<N>;
 
-// -------------- ARITHMATIC FUNCTIONS ---------------
+/// Average gifts of every type
+type GiftRanks<N = f64> = GiftMap<AverageRank<N>>;
 
-fn clamp(i:f64, min:f64, max:f64) -> f64 {
-    if i < min {
-        min
-    } else if i > max {
-        max
-    } else {
-        i
+/// Average gifts in each slot of a single monster, indexed by slot and then by gift type.
+/// Gifts that do not take a slot are always 0
+type SlotRanks<N = f64> = Vec<GiftRanks<N>>;
+
+/// Plot data that is only rebuilt when the settings change, and turned into plot items every frame
+#[derive(Default,Clone)]
+struct ChartCache {
+    bars:Vec<BarSeries>,
+    slot_total:Option<LineSeries>,
+    bounty_comparison:Vec<LineSeries>,
+    observed:Vec<ErrorBarSeries>,
+    spread:Vec<ErrorBarSeries>,
+    /// expected utility over karma with its optimum
+    utility:Option<(LineSeries, Optimum)>,
+    /// stacked bars of every slot for the per slot view
+    slots:Vec<Vec<BarSeries>>,
+    /// one bar per karma value and wonderful count, colored by the amount of gifts
+    heatmap:Option<BarSeries>,
+    dashboard:Vec<ChapterCharts>
+}
+
+/// Slot gifts and bounty gifts of one chapter in the dashboard
+#[derive(Clone)]
+struct ChapterCharts {
+    chapter:Chapter,
+    slots:Vec<BarSeries>,
+    bounty:Vec<BarSeries>,
+    /// highest stacked value of the slot gifts and of the bounty gifts, so all charts can share their axes
+    slot_top:f64,
+    bounty_top:f64
+}
+
+#[derive(Clone)]
+struct BarSeries {
+    name:String,
+    color:Color32,
+    bars:Vec<plt::Bar>
+}
+
+#[derive(Clone)]
+struct LineSeries {
+    name:String,
+    color:Color32,
+    style:plt::LineStyle,
+    width:f32,
+    points:Vec<[f64;2]>
+}
+
+/// Box plot elements used as error bars
+#[derive(Clone)]
+struct ErrorBarSeries {
+    name:String,
+    color:Color32,
+    elems:Vec<plt::BoxElem>
+}
+
+/// Part of the donut chart, split into pieces that are small enough to be drawn as convex polygons
+#[derive(Clone)]
+struct DonutSlice {
+    name:String,
+    color:Color32,
+    amount:f64,
+    pieces:Vec<Vec<[f64;2]>>,
+    /// middle of the ring at the center angle of the slice
+    label:[f64;2]
+}
+
+impl DonutSlice {
+    fn polygons(&self) -> Vec<plt::Polygon> {
+        self.pieces.iter().map(|piece| {
+            plt::Polygon::new(piece.clone())
+                .name(&self.name)
+                .fill_color(self.color)
+                .stroke(egui::Stroke::new(0.5, self.color))
+        }).collect()
     }
 }
 
-fn apply_probability(remaining:&mut [f64;3], chance:f64, factor:f64) -> f64 {
-    let mut total_added = 0.0;
-    let mut used_prob = 0.0;
-    for i in 0..=2 {
-        let current = (remaining[i] - used_prob) * chance *pow(factor,i);
-        used_prob += remaining[i] - used_prob;
-        //added to later slots if current slot was full
+impl ErrorBarSeries {
+    fn box_plot(&self) -> plt::BoxPlot {
+        plt::BoxPlot::new(self.elems.clone()).name(&self.name).color(self.color)
+    }
+}
 
-        //fill up current slot
-        total_added += current;
-        remaining[i] -= current;
+impl BarSeries {
+    fn chart(&self) -> plt::BarChart {
+        plt::BarChart::new(self.bars.clone()).name(&self.name).color(self.color)
     }
-    total_added
 }
 
-fn pow(f:f64, x:usize) -> f64 {
-    if x > 0 {
-        f*pow(f,x - 1)
-    } else {
-        1.0
+impl LineSeries {
+    fn line(&self) -> plt::Line {
+        plt::Line::new(self.points.clone())
+            .name(&self.name)
+            .color(self.color)
+            .style(self.style)
+            .width(self.width)
     }
 }
 
-fn bounty_average_rank(karma:f64, chapter:Chapter) -> AverageRank {
-    let mut result = [0.0, 0.0, 0.0];
-    let prob = gift_probabilities(karma, GType::Bounty, chapter);
-    result[0] = prob.chosen[0];
-    result[1] = result[0] * prob.rank_up[0];
-    result[2] = result[1] * prob.rank_up[1];
-    return result;
+/// Identifies the result of a chapter gift sequence, the karma and model profile are stored as bits
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct SequenceKey {
+    chapter:Chapter,
+    wonderful_count:usize,
+    karma:u64,
+    profile:u64,
+    /// hash of the custom orders, 0 for the default orders of the chapter
+    orders:u64
 }
 
-// ----------------- INITIAL BLESSINGS/BURDENS FOR TOWER ------------------
-fn tower_initial_sequence(karma:f64) -> ([f64;3], [AverageRank;6]) {
-    let blessing_prob = gift_probabilities(karma, GType::Blessing, Chapter::Towers);
-    let two_gift_chance = blessing_prob.chosen[1];
+const MAX_CACHED_SEQUENCES:usize = 100_000;
+
+/// Assumptions of the model that are community estimates rather than known game rules
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ModelProfile {
+    /// the game rules, only changed to model hypothetical or modded rules
+    #[serde(default)]
+    rules:GameRules,
+    /// formulas of each gift type per chapter, in the order of Chapter::ALL.
+    /// Stored under the name of every gift type that has its own probabilities, the other gift types have no formulas
+    #[serde(flatten, serialize_with = "serialize_formulas", deserialize_with = "deserialize_formulas")]
+    formulas:GiftMap<[GiftFormulas;CHAPTER_COUNT]>,
+    /// chance that a tower burden is one rank lower than the 2 or 3 star blessing it comes with
+    burden_rank_down:f64,
+    /// chance that a tower burden is 3 star if only the first of two blessings is 3 star
+    burden_three_star:f64,
+    /// part of the 2 star first power gifts in special towers that come with a burden
+    special_burden_rank2:f64,
+    /// part of the 3 star first power gifts in special towers that come with a burden
+    special_burden_rank3:f64
+}
 
-    //blessings
-    let mut blessing_ranks = [1.0, blessing_prob.rank_up[0], 0.0];
-    blessing_ranks[2] = blessing_ranks[1] * blessing_prob.rank_up[1];
+/// The amount of ranks, slots and tries that the game allows
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct GameRules {
+    /// ranks of every gift, the highest rank is the amount of stars of the best gift
+    ranks:usize,
+    /// gift slots of a monster
+    slots:usize,
+    /// times every gift type can be tried on a single monster
+    max_tries:usize
+}
 
-    //assume only one gift is found
-    let mut one_gift = [[0.0;3];6];
-    {
-        let blessing_rank2 = blessing_ranks[1] - blessing_ranks[2];
+/// The towers place gifts of up to 3 stars in the first 3 slots and the default orders try a gift type up to 3 times,
+/// so the rules can be extended but not reduced. Profiles with smaller values are rejected
+const MIN_RULE_VALUE:usize = 3;
+const MAX_RULE_VALUE:usize = 6;
 
-        //burdens have a 25% chance to have a rank one lower than the blessing
-        let mut burden_ranks = [1.0, 0.0, 0.0];
-        burden_ranks[1] = blessing_ranks[1] - blessing_rank2 * 0.25;
-        burden_ranks[2] = blessing_ranks[2] * 0.75;
+/// Index of the 3 star rank, the chance of a 3 star power gift is halved for every wonderful power gift
+const WONDERFUL_RANK:usize = 2;
 
-        one_gift[GType::Blessing as usize] = blessing_ranks;
-        one_gift[GType::Burden as usize] = burden_ranks;
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules { ranks: 3, slots: 3, max_tries: 3 }
     }
+}
 
-    //assume two gifts are found
-    let mut two_gifts = [[0.0;3];6];
-    {
-        // burdens are 3 star it the second gift is a 3 star
-        let mut burden_ranks = [1.0;3];
-        burden_ranks[2] = blessing_ranks[2];
+impl GameRules {
+    /// Rules with every value within the allowed range
+    fn clamped(&self) -> GameRules {
+        let clamp = |value:usize| value.clamp(MIN_RULE_VALUE, MAX_RULE_VALUE);
+        GameRules { ranks: clamp(self.ranks), slots: clamp(self.slots), max_tries: clamp(self.max_tries) }
+    }
 
-        //There is a 75% chance the burden will be a 3 star if the first blessing is a 3 star but the second isnt
-        burden_ranks[2] += blessing_ranks[2] * (1.0 - blessing_ranks[2]) * 0.75;
+    /// Rules from a file are rejected instead of clamped, so a profile never silently models other rules than it says
+    fn validate(&self) -> Result<(), String> {
+        for (value, name) in [(self.ranks, "ranks"), (self.slots, "slots"), (self.max_tries, "max_tries")] {
+            if !(MIN_RULE_VALUE..=MAX_RULE_VALUE).contains(&value) {
+                return Err(format!("{name} is {value}, the rules can only be {MIN_RULE_VALUE} to {MAX_RULE_VALUE}"));
+            }
+        }
+        Ok(())
+    }
 
-        two_gifts[GType::Blessing as usize] = blessing_ranks.map(|r| r*2.0);
-        two_gifts[GType::Burden as usize] = burden_ranks;
-        
+    fn no_ranks<N:Number>(&self) -> AverageRank<N> {
+        vec![N::zero(); self.ranks]
     }
 
-    let gift_ranks = merge(two_gifts, one_gift, two_gift_chance);
-    let remaining = [0.0, 0.0, 1.0 - two_gift_chance];
-    return (remaining, gift_ranks)
-}
+    fn no_gifts<N:Number>(&self) -> GiftRanks<N> {
+        GiftRanks::from_fn(|_| self.no_ranks())
+    }
 
-fn special_tower_initial_sequence(karma:f64, wonderful_count:usize) -> ([f64;3], [AverageRank;6]) {
-    let mut gift_ranks = [[0.0;3];6];
-    let power_prob = gift_probabilities(karma, GType::Power, Chapter::Towers);
+    /// Chance that every slot is still empty and the gifts in every slot before any gift is tried
+    fn empty_slots<N:Number>(&self) -> (Vec<N>, SlotRanks<N>) {
+        (vec![N::one(); self.slots], vec![self.no_gifts(); self.slots])
+    }
 
-    //blessings
-    let mut power_ranks = [1.0, power_prob.rank_up[0], 0.0];
-    power_ranks[2] = power_ranks[1] * power_prob.rank_up[1] * pow(0.5, wonderful_count);
+    /// Ranks of a gift that is found with the given chance, every rank is reached by ranking up from the rank below
+    fn rank_chain<N:Number>(&self, chance:N, rank_up:&[N]) -> AverageRank<N> {
+        let mut ranks = vec![chance];
+        for rank in 1..self.ranks {
+            ranks.push(ranks[rank - 1].clone() * rank_up[rank - 1].clone());
+        }
+        ranks
+    }
 
-    //burden split
-    let mut burden_ranks = [0.0;3];
-    burden_ranks[0] = power_ranks[1]/3.0;
-    burden_ranks[1] = power_ranks[2]/2.0;
-    burden_ranks[2] = burden_ranks[1];
+    /// Ranks that are written for the 3 ranks of the game, higher ranks are never reached
+    fn star_ranks<N:Number>(&self, ranks:[N;3]) -> AverageRank<N> {
+        let mut result = self.no_ranks();
+        result[..3].clone_from_slice(&ranks);
+        result
+    }
 
-    //burden merge
-    burden_ranks[1] += burden_ranks[2];
-    burden_ranks[0] += burden_ranks[1];
+    /// Name of the gifts with at least the given rank (starting at 0)
+    fn rank_name(&self, rank:usize) -> String {
+        match rank {
+            0 => String::new(),
+            _ if rank + 1 == self.ranks => format!("{} star", rank + 1),
+            _ => format!("{}+ star", rank + 1)
+        }
+    }
+}
 
-    //add results to the general list
-    gift_ranks[GType::Power as usize] = power_ranks;
-    gift_ranks[GType::Burden as usize] = burden_ranks;
+/// A probability of the form clamp(a + b*karma, lo, hi)
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Linear {
+    a:f64,
+    b:f64,
+    lo:f64,
+    hi:f64
+}
 
-    let remaining = [0.0, 1.0 - burden_ranks[0], 1.0];
-    return (remaining, gift_ranks);
+/// The probability formulas of a single gift type in a single chapter.
+/// Tries without a formula use the formula of the last try, rank ups without a formula never happen
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct GiftFormulas {
+    chosen:Vec<Linear>,
+    rank_up:Vec<Linear>
 }
 
-/// Calculates the average number of gifts of each type after several "Try add gift" actions in a specific order
-/// 
-fn try_gift_sequence(karma:f64, order:&[GType], wonderful_count:usize, chapter:Chapter) -> [AverageRank;6] {
-    //data per gift
-    let mut frequency = [0;6];
+impl Linear {
+    const fn new(a:f64, b:f64, lo:f64, hi:f64) -> Self {
+        Linear { a, b, lo, hi }
+    }
 
-    //towers and special towers remove a set amount of gifts at the start
-    let (mut remaining, mut result) = match chapter {
-        Chapter::Towers => tower_initial_sequence(karma),
-        Chapter::SpecialTowers => special_tower_initial_sequence(karma, wonderful_count),
-        _ => ([1.0; 3], [[0.0; 3]; 6])
-    };
+    const fn constant(value:f64) -> Self {
+        Linear { a: value, b: 0.0, lo: value, hi: value }
+    }
+
+    fn at<N:Number>(&self, karma:N) -> N {
+        clamp(N::from_f64(self.a) + N::from_f64(self.b) * karma, N::from_f64(self.lo), N::from_f64(self.hi))
+    }
 
-    //first power gift counts for blessings
-    if let Chapter::SpecialTowers = chapter {frequency[3] += 1}
+    fn parameters_mut(&mut self) -> [&mut f64;4] {
+        [&mut self.a, &mut self.b, &mut self.lo, &mut self.hi]
+    }
 
+    /// Whether the formula is always a probability, with the bounds in order
+    fn is_probability(&self) -> bool {
+        0.0 <= self.lo && self.lo <= self.hi && self.hi <= 1.0
+    }
+}
 
-    //for wonderful gifts, 1.0 - the chance you have i wonderful gifts
-    let mut w_remaining = [1.0,1.0,1.0];
+impl GiftFormulas {
+    fn at<N:Number>(&self, karma:N, rules:&GameRules) -> Probabilities<N> {
+        let last_try = self.chosen.last().copied().unwrap_or(Linear::constant(0.0));
+        Probabilities {
+            chosen: (0..rules.max_tries).map(|i| self.chosen.get(i).unwrap_or(&last_try).at(karma.clone())).collect(),
+            rank_up: (0..rules.ranks - 1).map(|i| self.rank_up.get(i).map_or(N::zero(), |f| f.at(karma.clone()))).collect()
+        }
+    }
 
-    for gift_elem in order {
-        let gift_index = *gift_elem as usize;
-        let gift_freq = frequency[gift_index];
-        if gift_freq >= 3 {
-            panic!("Gift {gift_elem:?} occurred in the order list more than 3 times!\nlist:{order:?}")
+    /// Adds the formulas of tries and rank ups that the rules allow, so they can be edited
+    fn extend_to(&mut self, rules:&GameRules) {
+        let last_try = self.chosen.last().copied().unwrap_or(Linear::constant(0.0));
+        if self.chosen.len() < rules.max_tries {
+            self.chosen.resize(rules.max_tries, last_try);
         }
-        let prob = gift_probabilities(karma, *gift_elem, chapter);
-        let gift_chance = prob.chosen[gift_freq];
-        let gifts_added = apply_probability(&mut remaining, gift_chance, 1.0);
+        if self.rank_up.len() < rules.ranks - 1 {
+            self.rank_up.resize(rules.ranks - 1, Linear::constant(0.0));
+        }
+    }
+}
 
-        result[gift_index][0] += gifts_added;
+impl ModelProfile {
+    /// Hash of all parameters, used to find cached results of this profile
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(self).expect("Profile can always be serialized").hash(&mut hasher);
+        hasher.finish()
+    }
 
-        //different rank calculations using gifts_added
-        let rank2added = gifts_added * prob.rank_up[0];
-        result[gift_index][1] +=  rank2added;
-        let rank3added = rank2added * prob.rank_up[1];
-        
+    /// The formulas of a gift type, empty for gift types without their own probabilities
+    fn formulas(&self, gift_type:GType, chapter:Chapter) -> &GiftFormulas {
+        &self.formulas[gift_type][chapter as usize]
+    }
 
-        if let GType::Power = gift_elem {
+    fn formulas_mut(&mut self, gift_type:GType, chapter:Chapter) -> &mut GiftFormulas {
+        &mut self.formulas[gift_type][chapter as usize]
+    }
 
-            //rank 3 gift chance is halved every time you receive another wonderful power gift
-            let rank3added = rank3added * pow(0.5,wonderful_count);
-            result[0][2] += apply_probability(&mut w_remaining, rank3added, 0.5);
-        } else {
-            result[gift_index][2] +=  rank3added;
+    /// Changes the game rules, the formulas of new tries and rank ups start out like the last try and as impossible
+    fn set_rules(&mut self, rules:GameRules) {
+        self.rules = rules.clamped();
+        let rules = self.rules;
+        for (_, chapters) in self.formulas.iter_mut().filter(|(gift_type, _)| gift_type.has_formulas()) {
+            for formulas in chapters {
+                formulas.extend_to(&rules);
+            }
+        }
+    }
+
+    /// Profiles are edited by hand, so rules, chances and formulas that are not probabilities are rejected
+    fn validate(&self) -> Result<(), String> {
+        self.rules.validate()?;
+        let burdens = [
+            ("burden_rank_down", self.burden_rank_down),
+            ("burden_three_star", self.burden_three_star),
+            ("special_burden_rank2", self.special_burden_rank2),
+            ("special_burden_rank3", self.special_burden_rank3)
+        ];
+        for (name, chance) in burdens {
+            if !(0.0..=1.0).contains(&chance) {
+                return Err(format!("{name} is {chance}, should be 0 to 1"));
+            }
+        }
+        for (gift_type, chapters) in self.formulas.iter().filter(|(gift_type, _)| gift_type.has_formulas()) {
+            for (chapter, formulas) in Chapter::ALL.into_iter().zip(chapters) {
+                let chosen = formulas.chosen.iter().enumerate().map(|(i, f)| (format!("try {}", i + 1), f));
+                let rank_up = formulas.rank_up.iter().enumerate().map(|(i, f)| (format!("rank up {}", i + 1), f));
+                if let Some((name, _)) = chosen.chain(rank_up).find(|(_, f)| !f.is_probability()) {
+                    return Err(format!("{} {} {name}: bounds should satisfy 0 <= lo <= hi <= 1", chapter.name(), gift_type.info().name));
+                }
+            }
         }
+        Ok(())
+    }
 
-        frequency[gift_index] += 1;
+    fn load(path:&str) -> Result<ModelProfile, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+        let mut profile:ModelProfile = serde_json::from_str(&text).map_err(|e| format!("Invalid profile {path}: {e}"))?;
+        profile.validate().map_err(|e| format!("Invalid profile {path}: {e}"))?;
+        profile.set_rules(profile.rules);
+        Ok(profile)
     }
 
-    //bounty gifts
-    result[5] = bounty_average_rank(karma, chapter);
+    fn save(&self, path:&str) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).expect("Profile can always be serialized");
+        std::fs::write(path, text).map_err(|e| format!("Could not write {path}: {e}"))
+    }
+}
 
+fn serialize_formulas<S:serde::Serializer>(formulas:&GiftMap<[GiftFormulas;CHAPTER_COUNT]>, serializer:S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(formulas.iter()
+        .filter(|(gift_type, _)| gift_type.has_formulas())
+        .map(|(gift_type, chapters)| (gift_type.name(), chapters)))
+}
 
-    return result;
+/// Other fields of the profile end up here as well, only the names of gift types with their own probabilities are read
+fn deserialize_formulas<'de, D:serde::Deserializer<'de>>(deserializer:D) -> Result<GiftMap<[GiftFormulas;CHAPTER_COUNT]>, D::Error> {
+    use serde::de::Error;
+    let mut fields:HashMap<String, serde_json::Value> = HashMap::deserialize(deserializer)?;
+    let mut formulas = GiftMap::<[GiftFormulas;CHAPTER_COUNT]>::default();
+    for gift_type in GType::ALL.into_iter().filter(|gift_type| gift_type.has_formulas()) {
+        let value = fields.remove(gift_type.name()).ok_or_else(|| D::Error::missing_field(gift_type.name()))?;
+        formulas[gift_type] = serde_json::from_value(value).map_err(|e| D::Error::custom(format!("{}: {e}", gift_type.name())))?;
+    }
+    Ok(formulas)
 }
-/// Creates a new list or averageranks by summing the elements of two lists.
-/// The factor argument is the factor of the first list, while 1.0 -factor1 is the factor of the second list
-/// Hence if you choose factor 0.5 the resulting list will be the average of the two lists
-fn merge(rankings1:[AverageRank;6], rankings2:[AverageRank;6], factor1:f64) -> [AverageRank;6] {
-    let mut result = [[0.0;3];6];
-    for i in 0..=5 {
-        for j in 0..=2 {
-            result[i][j] = factor1* rankings1[i][j] + (1.0 - factor1)*rankings2[i][j];
+
+impl Default for ModelProfile {
+    fn default() -> Self {
+        ModelProfile {
+            rules: GameRules::default(),
+            formulas: {
+                let chapters = Chapter::ALL.map(|chapter| chapter.model().formulas());
+                GiftMap::from_fn(|gift_type| chapters.each_ref().map(|formulas| formulas[gift_type].clone()))
+            },
+            burden_rank_down: 0.25,
+            burden_three_star: 0.75,
+            special_burden_rank2: 1.0/3.0,
+            special_burden_rank3: 0.5
         }
     }
-    result
 }
 
+/// Chances at one karma value, with an entry for every try and every rank up that the rules allow
+#[derive(Clone)]
+struct Probabilities<N = f64> {
+    chosen:Vec<N>,
+    rank_up:Vec<N>
+}
+
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash,Serialize,Deserialize)]
+#[serde(try_from = "String", into = "String")]
+enum GType {
+    Power,
+    Bonus,
+    Quick,
+    Blessing,
+    Burden,
+    Bounty
+}
 
-// ------------ PROGRAM ----------------
-impl PlotProgram {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        // egui_extras::install_image_loaders(&cc.egui_ctx);
-        cc.egui_ctx.set_visuals(egui::Visuals::dark());
-        // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state using cc.storage (requires the "persistence" feature).
-        // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
-        // for e.g. egui::PaintCallback.
-        let mut program = PlotProgram {
-            domain_settings:DomainSettings { min: 0, max: 200, step: 1 },
-            ..Self::default()};
-        program.recalc();
-        program
+impl GType {
+    const ALL:[GType;GIFT_COUNT] = {
+        let mut all = [GType::Power;GIFT_COUNT];
+        let mut i = 0;
+        while i < GIFT_COUNT {
+            //info() looks gift types up by their discriminant, so the registry has to be in the order of the enum
+            assert!(GIFTS[i].gift_type as usize == i, "GIFTS has to be in the order of GType");
+            all[i] = GIFTS[i].gift_type;
+            i += 1;
+        }
+        all
+    };
+
+    fn info(&self) -> &'static GiftInfo {
+        &GIFTS[*self as usize]
     }
 
-// ----------------------------- GIFT SEQUENCES -------------------------------------------
-    fn story_sequence(&self, i:f64) -> [AverageRank;6] {
-        let order1 = [GType::Power, GType::Power, GType::Power, GType::Bonus, GType::Bonus, GType::Quick, GType::Quick];
-        let order2 = [GType::Power, GType::Power, GType::Power, GType::Quick, GType::Bonus, GType::Quick, GType::Bonus];
-        return merge(
-            try_gift_sequence(i, &order1, self.wonderful_count, self.chapter), 
-            try_gift_sequence(i, &order2, self.wonderful_count, self.chapter),
-            0.5
-        );
+    fn name(&self) -> &'static str {
+        self.info().name
     }
 
-    fn alter_story_sequence(&self, i:f64) -> [AverageRank;6] {
-        let order = [GType::Bonus, GType::Quick];
-        try_gift_sequence(i, &order, 0, self.chapter)
+    /// Gift types that are offered next to the 3 gift slots
+    fn slotless() -> impl Iterator<Item = GType> {
+        GType::ALL.into_iter().filter(|gift_type| !gift_type.info().takes_slot)
     }
 
+    /// Gift types that fill the gift slots
+    fn slot_types() -> impl Iterator<Item = GType> {
+        GType::ALL.into_iter().filter(|gift_type| gift_type.info().takes_slot)
+    }
 
-// note that both the tower and special tower gift sequences do not include the initial blessing/burden combo    
-    fn tower_sequence(&self, i:f64) -> [AverageRank;6] {
-        let order1 = [GType::Bonus, GType::Quick];
-        let order2 = [GType::Quick, GType::Bonus];
-        return merge(
-            try_gift_sequence(i, &order1, 0, self.chapter),
-            try_gift_sequence(i, &order2, 0, self.chapter),
-            0.5
-        );        
+    /// Gift types that can be tried in an order
+    fn has_probabilities(&self) -> bool {
+        self.info().probabilities != ProbabilitySource::Placed
     }
 
-    fn special_tower_sequence(&self, i:f64) -> [AverageRank;6] {
-        let order1 = [GType::Blessing, GType::Bonus, GType::Bonus];
-        let order2 = [GType::Bonus, GType::Bonus, GType::Blessing];
-        return merge(
-            try_gift_sequence(i, &order1, self.wonderful_count, self.chapter), 
-            try_gift_sequence(i, &order2, self.wonderful_count, self.chapter),
-            0.5
-        );
+    /// Gift types with formulas of their own in the model profile
+    fn has_formulas(&self) -> bool {
+        self.info().probabilities == ProbabilitySource::Own
     }
 
-// -------------------------- TOP LEVEL CALCULATIONS -----------------------------------
-    fn recalc(&mut self) {
-        let settings = &self.domain_settings;
-        self.karma_range = (settings.min..=settings.max).step_by(settings.step as usize).collect();
-        self.recalc_giftchance();
+    fn from_name(name:&str) -> Option<GType> {
+        GType::ALL.into_iter().find(|gift_type| gift_type.name().eq_ignore_ascii_case(name.trim()))
     }
+}
 
-    fn recalc_giftchance(&mut self) {
-        let karma_range = &self.karma_range;
-        let mut power = Vec::new();
-        let mut bonus = Vec::new();
-        let mut quick = Vec::new();
-        let mut bounty = Vec::new();
-        let mut blessing = Vec::new();
-        let mut burden = Vec::new();
-        for i in karma_range {
-            let i = *i as f64 / 100.0;
-            let [power_elem, bonus_elem, quick_elem, bless_elem, burden_elem, bounty_elem] 
-                = match self.chapter {
-                    Chapter::Story => self.story_sequence(i),
-                    Chapter::AStory => self.alter_story_sequence(i),
-                    Chapter::Towers => self.tower_sequence(i),
-                    Chapter::SpecialTowers => self.special_tower_sequence(i)
-            };
+impl From<GType> for String {
+    fn from(gift_type:GType) -> String {
+        gift_type.name().to_string()
+    }
+}
 
-            power.push(power_elem);
-            bonus.push(bonus_elem);
-            quick.push(quick_elem);
-            blessing.push(bless_elem);
-            burden.push(burden_elem);
-            bounty.push(bounty_elem);
-        }
-        self.gift_chance = GiftChance {power, bonus, quick, blessing, burden, bounty};
+impl TryFrom<String> for GType {
+    type Error = String;
+    fn try_from(name:String) -> Result<Self, Self::Error> {
+        GType::from_name(&name).ok_or_else(|| format!("unknown gift type '{name}'"))
     }
+}
 
+impl Chapter {
+    /// Finds a chapter by its name, ignoring case, spaces and underscores
+    fn from_name(name:&str) -> Option<Chapter> {
+        let simplify = |name:&str| name.to_lowercase().replace([' ', '_'], "");
+        let name = simplify(name);
+        Chapter::ALL.into_iter().find(|chapter| simplify(chapter.name()) == name || simplify(&format!("{chapter:?}")) == name)
+    }
 
-// --------------------- CHARTS -----------------------------------------
-    fn gift_chart(&self, gift_type:GType, average_ranks:&Vec<AverageRank>, name:&str) -> [plt::BarChart;3] {
-        let simple_gift =       self.single_rank_chart(gift_color(gift_type,0), 0, &average_ranks, &format!("{name} 1 star"));
-        let lovely_gift =       self.single_rank_chart(gift_color(gift_type,1), 1, &average_ranks, &format!("{name} 2 star"));
-        let wonderful_gift =    self.single_rank_chart(gift_color(gift_type,2), 2, &average_ranks, &format!("{name} 3 star"));
-        [simple_gift, lovely_gift, wonderful_gift]
+    fn name(&self) -> &'static str {
+        self.model().name()
     }
 
-    fn single_rank_chart(&self, color:Color32,rank:usize, average_ranks:&Vec<AverageRank>, name:&str) -> plt::BarChart {
-        plt::BarChart::new(
-            self.karma_range.iter().enumerate().map(|(i,karma)| {
-                let width = self.domain_settings.step as f64;
-                plt::Bar::new(*karma as f64, average_ranks[i][rank])
-                    .width(width + 0.2)
-                    .fill(color)
-        }).collect()).name(name).color(color)
+    fn color(&self) -> Color32 {
+        Color32::from_hex(self.model().color()).unwrap()
     }
+}
 
+impl From<Chapter> for String {
+    fn from(chapter:Chapter) -> String {
+        chapter.name().to_string()
+    }
 }
 
+impl TryFrom<String> for Chapter {
+    type Error = String;
+    fn try_from(name:String) -> Result<Self, Self::Error> {
+        Chapter::from_name(&name).ok_or_else(|| format!("unknown chapter '{name}'"))
+    }
+}
 
-// ----------------------- USER INTERACTION -------------------------
+const EMPTY_COLOR:&str = "#4a4a4a";
 
 
-impl eframe::App for PlotProgram {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            //setting buttons
-            let mut recalc = false;
-            ui.horizontal(|ui| {
-                egui::ComboBox::from_label("Chapter")
-                    .selected_text(format!("{:?}",self.chapter))
-                    .show_ui(ui, |ui| {
-                        if ui.selectable_value(&mut self.chapter, Chapter::Story, "Story").clicked() {recalc = true};
-                        if ui.selectable_value(&mut self.chapter, Chapter::AStory, "Alter Story").clicked() {recalc = true};
-                        if ui.selectable_value(&mut self.chapter, Chapter::Towers, "Towers").clicked() {recalc = true};
-                        if ui.selectable_value(&mut self.chapter, Chapter::SpecialTowers, "Special Towers").clicked() {recalc = true};
-                    });
-                ui.label("|");
-                if ui.checkbox(&mut self.bounty_view, "View bounty gifts").changed() {recalc = true};
-            });
-            
+/// How much closer to white every rank above the colors of a gift type gets
+const EXTRA_RANK_LIGHTEN:f32 = 0.35;
 
-            ui.horizontal(|ui| {
-                let settings = &mut self.domain_settings;
-                
-                if ui.add(egui::DragValue::new(&mut settings.min)
-                    .clamp_range(0..=settings.max)
-                    .speed(1.0)
-                    .prefix("min: ")).changed() {
-                        recalc = true;
-                }
-                if ui.add(egui::DragValue::new(&mut settings.max)
-                    .clamp_range(settings.min..=300)
-                    .speed(1.0)
-                    .prefix("max: ")).changed() {
-                        recalc = true
-                }
-                if ui.add(egui::DragValue::new(&mut settings.step)
-                    .clamp_range(1..=(settings.max - settings.min))
-                    .speed(1.0)
-                    .prefix("step: ")).changed() {
-                        recalc = true
-                }
-                if ui.add(egui::DragValue::new(&mut self.wonderful_count)
-                    .clamp_range(0..=4)
-                    .speed(1.0)
-                    .prefix("gifts: ")).changed() {
-                        recalc = true
-                }
-                
-                
-            });
-            if recalc {self.recalc()}
+/// Ranks above the 3 star colors of a gift type get a lighter version of the 3 star color
+fn gift_color(gift_type:GType, rank:usize)-> Color32 {
+    let colors = gift_type.info().colors;
+    let color = egui::ecolor::Color32::from_hex(colors[rank.min(colors.len() - 1)]).unwrap();
+    let part = (rank.saturating_sub(colors.len() - 1) as f32 * EXTRA_RANK_LIGHTEN).min(1.0);
+    let lighten = |channel:u8| (channel as f32 + (255.0 - channel as f32) * part).round() as u8;
+    Color32::from_rgb(lighten(color.r()), lighten(color.g()), lighten(color.b()))
+}
 
-            // find barchart based on vector data
-            let power_gifts = self.gift_chart(GType::Power, &self.gift_chance.power,"power");
-            let mut blessing_gifts = self.gift_chart(GType::Blessing, &self.gift_chance.blessing,"blessing").map(|c| c.stack_on(&[&power_gifts[0]]));
-            let mut burden_gifts = self.gift_chart(GType::Burden, &self.gift_chance.burden,"burden").map(|c| c.stack_on(&[&blessing_gifts[0]]));
-            let mut bonus_gifts = self.gift_chart(GType::Bonus, &self.gift_chance.bonus,"bonus").map(|c| c.stack_on(&[&burden_gifts[0]]));
-            let quick_gifts = self.gift_chart(GType::Quick, &self.gift_chance.quick,"quick").map(|c| c.stack_on(&[&bonus_gifts[0]]));
-            
-            let bounty_gifts = self.gift_chart(GType::Bounty, &self.gift_chance.bounty, "bounty");
+/// Which gifts are shown in the main chart.
+/// Bounty gifts are independent of the 3 gift slots, so they are either shown on their own or on top of the slot gifts
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum GiftView {
+    #[default]
+    Slots,
+    Bounty,
+    Combined,
+    /// every slot on its own, to see which gifts end up in which slot
+    PerSlot,
+    /// small charts of every chapter next to each other
+    Dashboard
+}
+
+/// Amount of monsters (gift opportunities) in a chapter.
+/// This is either a known amount, or for the alter story boss fights the chance of getting 3, 4, 5 or 6 fights
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum MonsterCount {
+    Fixed(usize),
+    Distribution([f64;4])
+}
+
+/// Amount of monsters of every chapter, in the order of Chapter::ALL.
+/// Only the boss fights of alter story are known, the other chapters count as a single monster until they are set
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ChapterMonsters([MonsterCount;CHAPTER_COUNT]);
+
+impl Default for ChapterMonsters {
+    fn default() -> Self {
+        ChapterMonsters(Chapter::ALL.map(|chapter| chapter.model().monsters()))
+    }
+}
+
+impl ChapterMonsters {
+    fn of(&self, chapter:Chapter) -> &MonsterCount {
+        &self.0[chapter as usize]
+    }
+
+    fn of_mut(&mut self, chapter:Chapter) -> &mut MonsterCount {
+        &mut self.0[chapter as usize]
+    }
+}
+
+/// How the gifts of all monsters of a chapter are shown
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum ChapterView {
+    #[default]
+    PerMonster,
+    ChapterTotal,
+    AtLeastOne
+}
 
-            //different stack order for special tower mode
-            if let Chapter::SpecialTowers = self.chapter {
-                burden_gifts = burden_gifts.map(|c| c.stack_on(&[&power_gifts[0]]));
-                bonus_gifts = bonus_gifts.map(|c| c.stack_on(&[&burden_gifts[0]]));
-                blessing_gifts = blessing_gifts.map(|c| c.stack_on(&[&bonus_gifts[0]]));
+const MIN_BOSS_FIGHTS:usize = 3;
+const MAX_BOSS_FIGHTS:usize = 6;
+const MAX_MONSTERS:usize = 200;
+
+impl MonsterCount {
+    /// The chance of getting every amount of monsters
+    fn weights(&self) -> Vec<(usize, f64)> {
+        match self {
+            MonsterCount::Fixed(count) => vec![(*count, 1.0)],
+            MonsterCount::Distribution(weights) => {
+                let total:f64 = weights.iter().sum();
+                let weights = if total > 0.0 {weights.map(|w| w / total)} else {[0.25;4]};
+                weights.iter().enumerate().map(|(i, w)| (i + MIN_BOSS_FIGHTS, *w)).collect()
             }
+        }
+    }
+
+    /// Counts that can not be chosen in the ui are rejected
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            MonsterCount::Fixed(count) if !(1..=MAX_MONSTERS).contains(count) => Err(format!("{count} monsters, should be 1 to {MAX_MONSTERS}")),
+            MonsterCount::Distribution(weights) if weights.iter().any(|w| !(0.0..=1.0).contains(w)) => Err(format!("boss fight weights {weights:?} should be 0 to 1")),
+            _ => Ok(())
+        }
+    }
 
+    fn expected_monsters(&self) -> f64 {
+        self.weights().iter().map(|(count, w)| w * *count as f64).sum()
+    }
 
+    /// Chance that something with the given chance per monster happens at least once over all monsters
+    fn at_least_once(&self, chance:f64) -> f64 {
+        self.weights().iter()
+            .map(|(count, w)| w * (1.0 - pow(1.0 - chance, *count)))
+            .sum()
+    }
 
-            //set visibility of graphs
-            let mut chart_list = match self.chapter {
-                Chapter::Story => vec![power_gifts, bonus_gifts, quick_gifts],
-                Chapter::AStory => vec![bonus_gifts, quick_gifts],
-                Chapter::Towers => vec![blessing_gifts, burden_gifts, bonus_gifts, quick_gifts],
-                Chapter::SpecialTowers => vec![power_gifts, burden_gifts, blessing_gifts, bonus_gifts]
-            };
-            if self.bounty_view {chart_list = vec![bounty_gifts]}
-
-
-            egui_plot::Plot::new("my_plot")
-                .view_aspect(2.0)
-                .allow_drag(false)
-                .allow_scroll(false)
-                .allow_zoom(false)
-                .x_axis_label("karma")
-                .y_axis_label("average amount of gifts")
-                .legend(plt::Legend::default().position(plt::Corner::LeftTop))
-                .show(ui, |plot_ui| {
-                    for list in chart_list {
-                        for chart in list {
-                            plot_ui.bar_chart(chart);
-                        }
-                    }
-                });
+    /// Converts the ranks of a single monster to the chosen view over all monsters.
+    /// For the at least one view the ranks have to be the chance of at least one gift per monster, not the average
+    fn aggregate(&self, ranks:AverageRank, view:ChapterView) -> AverageRank {
+        match view {
+            ChapterView::PerMonster => ranks,
+            ChapterView::ChapterTotal => ranks.iter().map(|r| r * self.expected_monsters()).collect(),
+            ChapterView::AtLeastOne => ranks.iter().map(|r| self.at_least_once(*r)).collect()
+        }
+    }
+}
+
+// ------------------- GIFT SPECIFIC FUNCTIONS -----------------
+
+fn gift_probabilities<N:Number>(karma:N, gift_type:GType, chapter:Chapter, profile:&ModelProfile) -> Probabilities<N> {
+    match gift_type.info().probabilities {
+        ProbabilitySource::Own => profile.formulas(gift_type, chapter).at(karma, &profile.rules),
+        ProbabilitySource::Borrowed { chosen, rank_up } => Probabilities {
+            chosen: profile.formulas(chosen, chapter).at(karma.clone(), &profile.rules).chosen,
+            rank_up: profile.formulas(rank_up, chapter).at(karma, &profile.rules).rank_up },
+        ProbabilitySource::Placed => panic!("{} gifts do not have any probabilities", gift_type.name())
+    }
+}
+
+// -------------- ARITHMATIC FUNCTIONS ---------------
+
+fn clamp<N:PartialOrd>(i:N, min:N, max:N) -> N {
+    if i < min {
+        min
+    } else if i > max {
+        max
+    } else {
+        i
+    }
+}
+
+fn pow<N:Number>(f:N, x:usize) -> N {
+    (0..x).fold(N::one(), |product, _| product * f.clone())
+}
+
+/// Average ranks of a gift that does not take a slot, there is either one or none
+fn slotless_average_rank<N:Number>(karma:N, gift_type:GType, chapter:Chapter, profile:&ModelProfile) -> AverageRank<N> {
+    let prob = gift_probabilities(karma, gift_type, chapter, profile);
+    profile.rules.rank_chain(prob.chosen[0].clone(), &prob.rank_up)
+}
+
+/// Calculates the average number of gifts of each type after several "Try add gift" actions in a specific order
+/// 
+/// The second value is the average amount of slots that stay empty
+fn try_gift_sequence<N:Number>(karma:N, order:&[GType], wonderful_count:usize, chapter:Chapter, profile:&ModelProfile) -> (GiftRanks<N>, N) {
+    let (remaining, slots) = try_gift_slots(karma.clone(), order, wonderful_count, chapter, profile);
+    let mut result = slot_totals(&slots, &profile.rules);
+
+    //gifts next to the slots, like bounties
+    for gift_type in GType::slotless() {
+        result[gift_type] = slotless_average_rank(karma.clone(), gift_type, chapter, profile);
+    }
+
+    //every remaining value is the chance that slot is still empty
+    let empty_slots = number::sum(remaining);
+    (result, empty_slots)
+}
+
+/// Same as try_gift_sequence, but keeps track of the slot every gift ends up in.
+/// Also returns the chance that each slot is empty at the end
+fn try_gift_slots<N:Number>(karma:N, order:&[GType], wonderful_count:usize, chapter:Chapter, profile:&ModelProfile) -> (Vec<N>, SlotRanks<N>) {
+    //towers and special towers remove a set amount of gifts at the start
+    let model = chapter.model();
+    let (remaining, mut slots) = chapter.initial_sequence(karma.clone(), wonderful_count, profile);
+    let mut state = SlotState::from_remaining(&remaining);
+
+    //data per gift
+    let mut frequency = model.initial_tries();
+
+    for gift_elem in order {
+        let gift_freq = frequency[*gift_elem];
+        //the default orders are written for 3 tries, tries that the rules do not allow are skipped
+        if gift_freq >= profile.rules.max_tries {
+            continue;
+        }
+        let prob = gift_probabilities(karma.clone(), *gift_elem, chapter, profile);
+        //only power gifts can be wonderful
+        let wonderful = (*gift_elem == GType::Power).then_some(wonderful_count);
+        let slots_added = state.try_gift(prob.chosen[gift_freq].clone(), &prob.rank_up, wonderful);
+
+        for (slot, added) in slots.iter_mut().zip(slots_added) {
+            for (total, rank_added) in slot[*gift_elem].iter_mut().zip(added) {
+                *total += rank_added;
+            }
+        }
+
+        frequency[*gift_elem] += 1;
+    }
+    (state.remaining(), slots)
+}
+
+/// Amount of gifts with exactly every rank, from the amount with at least that rank
+fn exact_ranks(ranks:&[f64]) -> Vec<f64> {
+    ranks.iter().enumerate().map(|(rank, amount)| amount - ranks.get(rank + 1).unwrap_or(&0.0)).collect()
+}
+
+/// Total gifts of every type over all slots
+fn slot_totals<N:Number>(slots:&SlotRanks<N>, rules:&GameRules) -> GiftRanks<N> {
+    let mut result = rules.no_gifts();
+    for slot in slots {
+        for (total, ranks) in result.values_mut().zip(slot.values()) {
+            for (total, rank) in total.iter_mut().zip(ranks) {
+                *total += rank.clone();
+            }
+        }
+    }
+    result
+}
+// ----------------------------- GIFT SEQUENCES -------------------------------------------
+/// The orders in which a monster of a chapter tries to add gifts, the result is the average of all orders.
+/// note that both the tower and special tower orders do not include the initial blessing/burden combo
+fn default_orders(chapter:Chapter) -> Vec<Vec<GType>> {
+    chapter.model().orders()
+}
+
+/// The average gifts of a single monster in a chapter
+fn chapter_sequence(karma:f64, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile) -> GiftRanks {
+    ordered_sequence(karma, chapter, wonderful_count, profile, &default_orders(chapter)).0
+}
+
+/// The average gifts and empty slots of a single monster when every order in the list is equally likely
+fn ordered_sequence<N:Number>(karma:N, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>]) -> (GiftRanks<N>, N) {
+    let (mut result, mut empty_slots) = try_gift_sequence(karma.clone(), &orders[0], wonderful_count, chapter, profile);
+    for (i, order) in orders.iter().enumerate().skip(1) {
+        let factor = N::one() / N::from_f64((i + 1) as f64);
+        let (next, next_empty) = try_gift_sequence(karma.clone(), order, wonderful_count, chapter, profile);
+        result = merge(next, result, factor.clone());
+        empty_slots = factor.clone() * next_empty + (N::one() - factor) * empty_slots;
+    }
+    (result, empty_slots)
+}
+
+/// The average gifts in every slot of a single monster when every order in the list is equally likely,
+/// together with the chance each slot is empty
+fn ordered_slots<N:Number>(karma:N, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>]) -> (Vec<N>, SlotRanks<N>) {
+    let factor = N::one() / N::from_f64(orders.len() as f64);
+    let mut empty = vec![N::zero(); profile.rules.slots];
+    let mut result = vec![profile.rules.no_gifts(); profile.rules.slots];
+    for order in orders {
+        let (remaining, slots) = try_gift_slots(karma.clone(), order, wonderful_count, chapter, profile);
+        for (empty, remaining) in empty.iter_mut().zip(remaining) {
+            *empty += factor.clone() * remaining;
+        }
+        for (result, slot) in result.iter_mut().flat_map(|slot| slot.values_mut()).zip(slots.iter().flat_map(|slot| slot.values())) {
+            for (result, rank) in result.iter_mut().zip(slot) {
+                *result += factor.clone() * rank.clone();
+            }
+        }
+    }
+    (empty, result)
+}
+
+/// Reads orders written as gift names separated by spaces, with | between the orders.
+/// Every gift type can only be tried as often as the rules allow per order, including the tries of the initial sequence of the chapter
+fn parse_orders(text:&str, chapter:Chapter, rules:&GameRules) -> Result<Vec<Vec<GType>>, String> {
+    let mut orders = Vec::new();
+    for order_text in text.split('|') {
+        let mut order = Vec::new();
+        for name in order_text.split_whitespace() {
+            let gift_type = GType::from_name(name).ok_or_else(|| format!("unknown gift type '{name}'"))?;
+            if !gift_type.info().takes_slot || !gift_type.has_probabilities() {
+                return Err(format!("{name} gifts can not be part of an order"));
+            }
+            order.push(gift_type);
+        }
+        if order.is_empty() {
+            return Err("orders can not be empty".to_string());
+        }
+        for gift_type in GType::ALL {
+            let tries = order.iter().filter(|g| **g == gift_type).count() + chapter.model().initial_tries()[gift_type];
+            if tries > rules.max_tries {
+                return Err(format!("{} gifts are tried more than {} times", gift_type.name(), rules.max_tries));
+            }
+        }
+        orders.push(order);
+    }
+    Ok(orders)
+}
+
+fn orders_text(orders:&[Vec<GType>]) -> String {
+    orders.iter()
+        .map(|order| order.iter().map(|g| g.name()).collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Creates a new list or averageranks by summing the elements of two lists.
+/// The factor argument is the factor of the first list, while 1.0 -factor1 is the factor of the second list
+/// Hence if you choose factor 0.5 the resulting list will be the average of the two lists
+fn merge<N:Number>(rankings1:GiftRanks<N>, rankings2:GiftRanks<N>, factor1:N) -> GiftRanks<N> {
+    GiftRanks::from_fn(|gift_type| {
+        let (ranks1, ranks2) = (&rankings1[gift_type], &rankings2[gift_type]);
+        ranks1.iter().zip(ranks2).map(|(rank1, rank2)| factor1.clone()* rank1.clone() + (N::one() - factor1.clone())*rank2.clone()).collect()
+    })
+}
+
+
+// ------------ PROGRAM ----------------
+impl PlotProgram {
+    fn new(cc: &eframe::CreationContext<'_>, files:StartupFiles) -> Self {
+        // egui_extras::install_image_loaders(&cc.egui_ctx);
+        cc.egui_ctx.set_visuals(egui::Visuals::dark());
+        // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
+        // Restore app state using cc.storage (requires the "persistence" feature).
+        // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
+        // for e.g. egui::PaintCallback.
+        let mut program = PlotProgram {
+            observation_settings:ObservationSettings { bin_width: 0.1, show: true, ..Default::default() },
+            ..Self::default()};
+        if let Some(path) = files.observations {
+            program.observation_settings.path = path;
+            program.load_observations();
+        }
+        if let Some(path) = files.profile {
+            program.profile_path = path;
+            program.load_profile();
+        }
+        if let Some(name) = files.scenario {
+            match scenario::find(&name).and_then(|scenario| scenario.apply(&mut program).map(|_| scenario)) {
+                Ok(scenario) => {
+                    program.scenarios.name = scenario.name;
+                    program.scenarios.open = true;
+                },
+                Err(error) => program.scenarios.status = error
+            }
+        }
+        if let Some(code) = files.code {
+            match Scenario::from_code(&code).and_then(|scenario| scenario.apply(&mut program)) {
+                Ok(()) => {},
+                Err(error) => {
+                    program.scenarios.status = error;
+                    program.scenarios.open = true;
+                }
+            }
+        }
+        program.scenarios.refresh();
+        program.utility.refresh();
+        program.recalc();
+        program
+    }
+
+// -------------------------- TOP LEVEL CALCULATIONS -----------------------------------
+    fn recalc(&mut self) {
+        self.fit_to_rules();
+        self.update_orders();
+        self.karma_range = self.domain_settings.karma_range();
+        let settings = &self.observation_settings;
+        self.empirical = observations::empirical_bins(&self.observations, self.chapter, self.wonderful_count, settings.bin_width, settings.interval, &self.profile.rules);
+        self.recalc_giftchance();
+    }
+
+    /// Settings that pick a rank are kept within the ranks of the rules, and every rank gets a utility value
+    fn fit_to_rules(&mut self) {
+        let rules = self.profile.rules;
+        self.spread_rank = self.spread_rank.min(rules.ranks - 1);
+        self.heatmap.rank = self.heatmap.rank.min(rules.ranks - 1);
+        self.utility.weights.extend_to(rules.ranks);
+    }
+
+    /// Custom orders depend on the chapter, so they are read again on every recalculation
+    fn update_orders(&mut self) {
+        if self.orders_text.trim().is_empty() {
+            self.custom_orders = None;
+            self.orders_status.clear();
+            return;
+        }
+        match parse_orders(&self.orders_text, self.chapter, &self.profile.rules) {
+            Ok(orders) => {
+                self.custom_orders = Some(orders);
+                self.orders_status.clear();
+            },
+            Err(error) => {
+                self.custom_orders = None;
+                self.orders_status = format!("{error}, using the default orders");
+            }
+        }
+    }
+
+    fn load_profile(&mut self) {
+        match ModelProfile::load(&self.profile_path) {
+            Ok(profile) => {
+                self.profile = profile;
+                self.profile_status = "profile loaded".to_string();
+            },
+            Err(error) => self.profile_status = error
+        }
+    }
+
+    fn load_observations(&mut self) {
+        let settings = &mut self.observation_settings;
+        match observations::load_observations(&settings.path) {
+            Ok(observations) => {
+                settings.status = format!("{} monsters from {} runs loaded", observations.len(), observations::run_count(&observations));
+                self.observations = observations;
+            },
+            Err(error) => settings.status = error
+        }
+    }
+
+    fn recalc_giftchance(&mut self) {
+        self.profile_key = self.profile.fingerprint();
+        self.gift_chance = self.chapter_gift_chance();
+        self.recalc_heatmap();
+        self.recalc_dashboard();
+        self.rebuild_charts();
+    }
+
+    /// The gifts of the current chapter for every karma value
+    fn chapter_gift_chance(&mut self) -> GiftChance {
+        let karma_range = self.karma_range.clone();
+        let mut ranks = GiftMap::<Vec<AverageRank>>::default();
+        let mut empty = Vec::new();
+        for i in karma_range {
+            let (gift_ranks, empty_slots) = self.chart_values(i, self.wonderful_count);
+            for (gift_type, gift_rank) in gift_ranks.iter() {
+                ranks[gift_type].push(gift_rank.clone());
+            }
+            empty.push(empty_slots);
+        }
+        GiftChance {ranks, empty}
+    }
+
+    /// The gifts of every chapter with the same settings.
+    /// Custom orders are written for the current chapter, so the other chapters use their default orders
+    fn recalc_dashboard(&mut self) {
+        self.dashboard.clear();
+        if self.view != GiftView::Dashboard {
+            return;
+        }
+        let chapter = self.chapter;
+        let custom_orders = self.custom_orders.take();
+        for other in Chapter::ALL {
+            if other == chapter {
+                self.dashboard.push((other, self.gift_chance.clone()));
+                continue;
+            }
+            self.chapter = other;
+            let chance = self.chapter_gift_chance();
+            self.dashboard.push((other, chance));
+        }
+        self.chapter = chapter;
+        self.custom_orders = custom_orders;
+    }
+
+    /// The chosen gift for every wonderful count, only calculated while the heatmap is shown
+    fn recalc_heatmap(&mut self) {
+        if !self.heatmap.show {
+            self.heatmap.values.clear();
+            return;
+        }
+        let karma_range = self.karma_range.clone();
+        let (gift_type, rank) = (self.heatmap.gift_type, self.heatmap.rank);
+        self.heatmap.values = (0..=MAX_WONDERFUL_COUNT).map(|wonderful_count| {
+            karma_range.iter()
+                .map(|karma| self.chart_values(*karma, wonderful_count).0[gift_type][rank])
+                .collect()
+        }).collect();
+    }
+
+    /// The gifts and empty slots at one karma value, in the chosen chapter view
+    fn chart_values(&mut self, karma:f64, wonderful_count:usize) -> (GiftRanks, f64) {
+        let (gift_ranks, empty_slots) = self.sequence(karma, wonderful_count);
+        let monsters = *self.monsters.of(self.chapter);
+
+        //the gifts of all monsters can be combined over the whole chapter
+        let gift_ranks = if self.chapter_view == ChapterView::AtLeastOne {
+            self.at_least_one_chances(karma, wonderful_count)
+        } else {
+            gift_ranks
+        };
+        let gift_ranks = gift_ranks.map(|ranks| monsters.aggregate(ranks, self.chapter_view));
+        let empty_slots = match self.chapter_view {
+            ChapterView::ChapterTotal => empty_slots * monsters.expected_monsters(),
+            _ => empty_slots
+        };
+        (gift_ranks, empty_slots)
+    }
+
+    fn orders(&self) -> Vec<Vec<GType>> {
+        self.custom_orders.clone().unwrap_or_else(|| default_orders(self.chapter))
+    }
+
+    /// Chance that a single monster has at least one gift of every type and rank.
+    /// This is only the average if a monster can not get more than one gift of a type
+    fn at_least_one_chances(&self, karma:f64, wonderful_count:usize) -> GiftRanks {
+        let orders = self.orders();
+        GiftRanks::from_fn(|gift_type| (0..self.profile.rules.ranks).map(|rank| {
+            let distribution = distribution::monster_distribution(karma, self.chapter, wonderful_count, &self.profile, &orders, gift_type, rank);
+            1.0 - distribution[0]
+        }).collect())
+    }
+
+    fn orders_key(&self) -> u64 {
+        match &self.custom_orders {
+            Some(orders) => {
+                let mut hasher = DefaultHasher::new();
+                orders.hash(&mut hasher);
+                hasher.finish()
+            },
+            None => 0
+        }
+    }
+
+    /// The gift sequence of the current chapter, only calculated if it is not in the cache yet
+    fn sequence(&mut self, karma:f64, wonderful_count:usize) -> (GiftRanks, f64) {
+        let key = SequenceKey {
+            chapter: self.chapter,
+            wonderful_count,
+            karma: karma.to_bits(),
+            profile: self.profile_key,
+            orders: self.orders_key()
+        };
+        if let Some(result) = self.sequence_cache.get(&key) {
+            return result.clone();
+        }
+
+        let default_orders = default_orders(self.chapter);
+        let orders = self.custom_orders.as_ref().unwrap_or(&default_orders);
+        let result = ordered_sequence(karma, self.chapter, wonderful_count, &self.profile, orders);
+        if self.sequence_cache.len() >= MAX_CACHED_SEQUENCES {
+            self.sequence_cache.clear();
+        }
+        self.sequence_cache.insert(key, result.clone());
+        result
+    }
+
+
+// --------------------- CHARTS -----------------------------------------
+    fn rebuild_charts(&mut self) {
+        self.charts = self.build_charts();
+        self.rebuild_table();
+        self.rebuild_cursor();
+    }
+
+    fn rebuild_table(&mut self) {
+        let columns = self.table.columns(self);
+        self.table.rows = self.table.rows(&columns);
+        self.table.data = columns;
+    }
+
+    /// Gift types that are part of the current view, from the bottom of the stack to the top
+    fn shown_gift_types(&self) -> Vec<GType> {
+        let mut gift_types = Vec::new();
+        if self.view != GiftView::Bounty {
+            gift_types.extend(self.stacked_gifts().into_iter().map(|(gift_type, _, _)| gift_type));
+        }
+        if let GiftView::Bounty | GiftView::Combined = self.view {
+            gift_types.extend(GType::slotless());
+        }
+        gift_types
+    }
+
+    /// The values at the cursor karma, only calculated while the cursor or the donut is shown
+    fn rebuild_cursor(&mut self) {
+        if !self.cursor.show && !self.show_donut {
+            self.cursor.values = CursorValues::default();
+            return;
+        }
+        let karma = self.cursor.karma;
+        let gift_types = self.shown_gift_types();
+        let (gift_ranks, empty_slots) = self.chart_values(karma, self.wonderful_count);
+        //burdens are only placed by the tower sequences and have no probabilities
+        let probabilities = gift_types.iter()
+            .filter(|gift_type| gift_type.has_probabilities())
+            .map(|gift_type| (*gift_type, gift_probabilities(karma, *gift_type, self.chapter, &self.profile)))
+            .collect();
+        let donut = if self.show_donut {self.donut_slices()} else {Vec::new()};
+        self.cursor.values = CursorValues { gift_types, gift_ranks, empty_slots, probabilities, donut };
+    }
+
+    fn move_cursor(&mut self, karma:f64) {
+        self.cursor.karma = karma;
+        self.rebuild_cursor();
+    }
+
+    /// Builds all bars and lines of the current settings, with the gift types stacked on top of each other
+    fn build_charts(&self) -> ChartCache {
+        if self.view == GiftView::PerSlot {
+            return ChartCache { slots: self.slot_charts(), utility: self.utility_chart(), heatmap: self.heatmap_chart(), ..Default::default() };
+        }
+        if self.view == GiftView::Dashboard {
+            return ChartCache { dashboard: self.dashboard_charts(), utility: self.utility_chart(), heatmap: self.heatmap_chart(), ..Default::default() };
+        }
+
+        let mut base = vec![0.0; self.karma_range.len()];
+        let mut bars = Vec::new();
+        if self.view != GiftView::Bounty {
+            for (gift_type, average_ranks, name) in self.stacked_gifts() {
+                bars.extend(self.gift_chart(gift_type, average_ranks, name, &base));
+                for (base, ranks) in base.iter_mut().zip(average_ranks) {
+                    *base += ranks[0];
+                }
+            }
+        }
+
+        //bounty gifts are either shown on their own or on top of the slot gifts
+        let mut slot_total = None;
+        if self.view != GiftView::Slots {
+            if self.view == GiftView::Combined {
+                slot_total = Some(self.slot_total_line(&base));
+            }
+            for gift_type in GType::slotless() {
+                let average_ranks = self.gift_chance.of(gift_type);
+                bars.extend(self.gift_chart(gift_type, average_ranks, gift_type.name(), &base));
+                for (base, ranks) in base.iter_mut().zip(average_ranks) {
+                    *base += ranks[0];
+                }
+            }
+        }
+
+        //empty slots go on top of everything, so the other gifts stay at the same height
+        if self.empty_slots_shown() {
+            bars.push(self.empty_slot_chart(&self.gift_chance.empty, &base));
+        }
+
+        let bounty_comparison = if self.view != GiftView::Slots {
+            self.slotless_comparison_lines()
+        } else {
+            Vec::new()
+        };
+
+        //observed averages only match the model for single monsters
+        let per_monster = self.chapter_view == ChapterView::PerMonster;
+        let observed = if self.observation_settings.show && per_monster {
+            self.observed_charts()
+        } else {
+            Vec::new()
+        };
+
+        let spread = if self.spread_shown() {
+            self.spread_charts()
+        } else {
+            Vec::new()
+        };
+
+        let utility = self.utility_chart();
+
+        let heatmap = self.heatmap_chart();
+        ChartCache { bars, slot_total, bounty_comparison, observed, spread, utility, slots: Vec::new(), heatmap, dashboard: Vec::new() }
+    }
+
+    /// The chance of at least one gift over all fights can not be added up into a utility
+    fn utility_chart(&self) -> Option<(LineSeries, Optimum)> {
+        if !self.utility.show || self.chapter_view == ChapterView::AtLeastOne {
+            return None;
+        }
+        let curve = self.utility.weights.curve(&self.gift_chance);
+        let optimum = utility::optimum(&curve)?;
+        let points = self.karma_range.iter().zip(&curve).map(|(karma, utility)| [self.x_value(*karma), *utility]).collect();
+        let line = LineSeries { name: "expected utility".to_string(), color: Color32::WHITE, style: plt::LineStyle::Solid, width: 1.5, points };
+        Some((line, optimum))
+    }
+
+    /// Cells of the heatmap, the name of every cell holds its value so it is shown when hovering
+    fn heatmap_chart(&self) -> Option<BarSeries> {
+        if self.heatmap.values.is_empty() {
+            return None;
+        }
+        let (min, max) = self.heatmap.range();
+        let bars = self.heatmap.values.iter().enumerate().flat_map(|(wonderful_count, values)| {
+            self.karma_range.iter().zip(values).map(move |(karma, value)| {
+                let part = if max > min {(value - min) / (max - min)} else {0.0};
+                plt::Bar::new(self.x_value(*karma), 1.0)
+                    .base_offset(wonderful_count as f64 - 0.5)
+                    .width(self.bar_width())
+                    .fill(heatmap::heat_color(part))
+                    .name(format!("{value:.4}"))
+            })
+        }).collect();
+        let name = format!("{} {}", self.heatmap.gift_type.name(), self.profile.rules.rank_name(self.heatmap.rank));
+        Some(BarSeries { name: name.trim().to_string(), color: heatmap::heat_color(1.0), bars })
+    }
+
+    /// A chance of at least one gift over all fights has no spread
+    fn spread_shown(&self) -> bool {
+        self.show_spread && self.chapter_view != ChapterView::AtLeastOne
+    }
+
+    /// Error bars on the stacked gifts of the chosen rank, the box is one standard deviation
+    /// around the average and the whiskers go from the 10th to the 90th percentile
+    fn spread_charts(&self) -> Vec<ErrorBarSeries> {
+        let default_orders = default_orders(self.chapter);
+        let orders = self.custom_orders.as_ref().unwrap_or(&default_orders);
+        let chapter_total = self.chapter_view == ChapterView::ChapterTotal;
+        let rank = self.spread_rank;
+        let distribution = |karma:f64, gift_type:Option<GType>| {
+            let fight = match gift_type {
+                Some(gift_type) => distribution::monster_distribution(karma, self.chapter, self.wonderful_count, &self.profile, orders, gift_type, rank),
+                None => distribution::empty_slot_distribution(karma, self.chapter, self.wonderful_count, &self.profile, orders)
+            };
+            if chapter_total {
+                distribution::chapter_distribution(&fight, self.monsters.of(self.chapter))
+            } else {
+                fight
+            }
+        };
+
+        let mut base = vec![0.0; self.karma_range.len()];
+        let mut series = Vec::new();
+        for gift_type in self.shown_gift_types() {
+            let averages = self.gift_chance.of(gift_type);
+            let elems = self.karma_range.iter().zip(&base)
+                .map(|(karma, base)| self.spread_elem(*karma, *base, Spread::of(&distribution(*karma, Some(gift_type)))))
+                .collect();
+            series.push(ErrorBarSeries { name: format!("{} spread", gift_type.name()), color: gift_color(gift_type, 2), elems });
+            for (base, ranks) in base.iter_mut().zip(averages) {
+                *base += ranks[0];
+            }
+        }
+        if self.empty_slots_shown() {
+            let elems = self.karma_range.iter().zip(&base)
+                .map(|(karma, base)| self.spread_elem(*karma, *base, Spread::of(&distribution(*karma, None))))
+                .collect();
+            series.push(ErrorBarSeries { name: "empty slots spread".to_string(), color: Color32::from_hex(EMPTY_COLOR).unwrap(), elems });
+        }
+        series
+    }
+
+    fn spread_elem(&self, karma:f64, base:f64, spread:Spread) -> plt::BoxElem {
+        let deviation = spread.variance.sqrt();
+        let box_spread = plt::BoxSpread::new(
+            base + spread.low,
+            base + f64::max(spread.mean - deviation, 0.0),
+            base + spread.mean,
+            base + spread.mean + deviation,
+            base + spread.high);
+        let width = self.bar_width() * 0.5;
+        plt::BoxElem::new(self.x_value(karma), box_spread)
+            .name(format!("standard deviation {deviation:.2}\n{:.0}th to {:.0}th percentile: {} to {}",
+                LOW_PERCENTILE * 100.0, HIGH_PERCENTILE * 100.0, spread.low, spread.high))
+            .box_width(width)
+            .whisker_width(width)
+            .fill(Color32::TRANSPARENT)
+            .stroke(egui::Stroke::new(1.0, Color32::WHITE))
+    }
+
+    /// For every slot the chance it is filled, split by the gift type and rank that fills it
+    fn slot_charts(&self) -> Vec<Vec<BarSeries>> {
+        let default_orders = default_orders(self.chapter);
+        let orders = self.custom_orders.as_ref().unwrap_or(&default_orders);
+        let slot_ranks:Vec<(Vec<f64>, SlotRanks)> = self.karma_range.iter()
+            .map(|karma| ordered_slots(*karma, self.chapter, self.wonderful_count, &self.profile, orders))
+            .collect();
+
+        (0..self.profile.rules.slots).map(|slot| {
+            let mut base = vec![0.0; self.karma_range.len()];
+            let mut bars = Vec::new();
+            for (gift_type, _, name) in self.stacked_gifts() {
+                let average_ranks:Vec<AverageRank> = slot_ranks.iter().map(|(_, slots)| slots[slot][gift_type].clone()).collect();
+                bars.extend(self.gift_chart(gift_type, &average_ranks, name, &base));
+                for (base, ranks) in base.iter_mut().zip(&average_ranks) {
+                    *base += ranks[0];
+                }
+            }
+            if self.show_empty {
+                let empty:Vec<f64> = slot_ranks.iter().map(|(empty, _)| empty[slot]).collect();
+                bars.push(self.empty_slot_chart(&empty, &base));
+            }
+            bars
+        }).collect()
+    }
+
+    /// Observed averages with their confidence intervals, stacked in the same order as the model
+    fn observed_charts(&self) -> Vec<ErrorBarSeries> {
+        let width = self.observation_settings.bin_width * self.domain_settings.unit.scale() * 0.3;
+        let mut base = vec![0.0; self.empirical.len()];
+        let mut series = Vec::new();
+        for gift_type in self.shown_gift_types() {
+            for rank in 0..self.profile.rules.ranks {
+                let color = gift_color(gift_type, rank);
+                let elems = self.empirical.iter().zip(&base).map(|(bin, base)| {
+                    let mean = base + bin.mean[gift_type][rank];
+                    let spread = plt::BoxSpread::new(base + bin.low[gift_type][rank], mean, mean, mean, base + bin.high[gift_type][rank]);
+                    plt::BoxElem::new(self.x_value(bin.karma), spread)
+                        .name(format!("{} monsters", bin.monsters))
+                        .box_width(width)
+                        .whisker_width(width)
+                        .fill(color)
+                        .stroke(egui::Stroke::new(2.0, color))
+                }).collect();
+                series.push(ErrorBarSeries { name: format!("observed {} {} star", gift_type.name(), rank + 1), color, elems });
+            }
+            for (base, bin) in base.iter_mut().zip(&self.empirical) {
+                *base += bin.mean[gift_type][0];
+            }
+        }
+        series
+    }
+
+    /// The visible slot gifts of the current chapter, from the bottom of the stack to the top
+    fn stacked_gifts(&self) -> Vec<(GType, &Vec<AverageRank>, &'static str)> {
+        let chance = &self.gift_chance;
+        self.stack_types(self.chapter).into_iter().map(|gift_type| (gift_type, chance.of(gift_type), gift_type.name())).collect()
+    }
+
+    fn stack_types(&self, chapter:Chapter) -> Vec<GType> {
+        let mut stack = chapter.model().stack();
+        //custom orders can try gift types the chapter normally does not have
+        if chapter == self.chapter {
+            for gift_type in self.custom_orders.iter().flatten().flatten() {
+                if !stack.contains(gift_type) {
+                    stack.push(*gift_type);
+                }
+            }
+        }
+        stack
+    }
+
+    /// Stacked slot gifts and bounty gifts of every chapter, with the same settings as the current chapter
+    fn dashboard_charts(&self) -> Vec<ChapterCharts> {
+        self.dashboard.iter().map(|(chapter, chance)| {
+            let mut base = vec![0.0; self.karma_range.len()];
+            let mut slots = Vec::new();
+            for gift_type in self.stack_types(*chapter) {
+                let average_ranks = chance.of(gift_type);
+                slots.extend(self.gift_chart(gift_type, average_ranks, gift_type.name(), &base));
+                for (base, ranks) in base.iter_mut().zip(average_ranks) {
+                    *base += ranks[0];
+                }
+            }
+            let mut slot_top = base.iter().fold(0.0, |top:f64, value| top.max(*value));
+            if self.empty_slots_shown() {
+                slots.push(self.empty_slot_chart(&chance.empty, &base));
+                slot_top = base.iter().zip(&chance.empty).fold(0.0, |top:f64, (base, empty)| top.max(base + empty));
+            }
+            let mut bounty_base = vec![0.0; self.karma_range.len()];
+            let mut bounty = Vec::new();
+            for gift_type in GType::slotless() {
+                let average_ranks = chance.of(gift_type);
+                bounty.extend(self.gift_chart(gift_type, average_ranks, gift_type.name(), &bounty_base));
+                for (base, ranks) in bounty_base.iter_mut().zip(average_ranks) {
+                    *base += ranks[0];
+                }
+            }
+            let bounty_top = bounty_base.iter().fold(0.0, |top:f64, value| top.max(*value));
+            ChapterCharts { chapter: *chapter, slots, bounty, slot_top, bounty_top }
+        }).collect()
+    }
+
+    fn gift_chart(&self, gift_type:GType, average_ranks:&[AverageRank], name:&str, base:&[f64]) -> Vec<BarSeries> {
+        (0..self.profile.rules.ranks)
+            .map(|rank| self.single_rank_chart(gift_color(gift_type, rank), rank, average_ranks, base, &format!("{name} {} star", rank + 1)))
+            .collect()
+    }
+
+    /// Line separating the slot gifts from the bounty gifts stacked on top of them
+    fn slot_total_line(&self, slot_total:&[f64]) -> LineSeries {
+        let half_width = self.bar_width() / 2.0;
+        let points:Vec<[f64;2]> = self.karma_range.iter().zip(slot_total).flat_map(|(karma, total)| {
+            let x = self.x_value(*karma);
+            [[x - half_width, *total], [x + half_width, *total]]
+        }).collect();
+        LineSeries { name: "slot total".to_string(), color: Color32::WHITE, style: plt::LineStyle::Solid, width: 1.5, points }
+    }
+
+    /// One line per chapter and slotless gift type with the average amount of gifts, and a dashed line for the 3 star gifts
+    fn slotless_comparison_lines(&self) -> Vec<LineSeries> {
+        let mut lines = Vec::new();
+        for gift_type in GType::slotless() {
+            for chapter in Chapter::ALL {
+                let ranks:Vec<AverageRank> = self.karma_range.iter()
+                    .map(|karma| slotless_average_rank(*karma, gift_type, chapter, &self.profile))
+                    .collect();
+                for (rank, style) in [(0, plt::LineStyle::Solid), (WONDERFUL_RANK, plt::LineStyle::dashed_loose())] {
+                    let points:Vec<[f64;2]> = self.karma_range.iter().zip(&ranks)
+                        .map(|(karma, ranks)| [self.x_value(*karma), ranks[rank]])
+                        .collect();
+                    let name = format!("{} {}", chapter.name(), gift_type.name());
+                    lines.push(LineSeries { name, color: chapter.color(), style, width: 1.0, points });
+                }
+            }
+        }
+        lines
+    }
+
+    /// Position of a karma value on the x axis
+    fn x_value(&self, karma:f64) -> f64 {
+        karma * self.domain_settings.unit.scale()
+    }
+
+    /// Bars are slightly wider than the step so there are no gaps between them
+    fn bar_width(&self) -> f64 {
+        (self.domain_settings.step + 0.002) * self.domain_settings.unit.scale()
+    }
+
+    fn empty_slot_chart(&self, empty:&[f64], base:&[f64]) -> BarSeries {
+        let color = Color32::from_hex(EMPTY_COLOR).unwrap();
+        let bars = self.karma_range.iter().enumerate().map(|(i, karma)| {
+            plt::Bar::new(self.x_value(*karma), empty[i])
+                .base_offset(base[i])
+                .width(self.bar_width())
+                .fill(color)
+        }).collect();
+        BarSeries { name: "empty slots".to_string(), color, bars }
+    }
+
+    /// Empty slots are only shown together with the slot gifts, and can not be combined into a chance over all fights
+    fn empty_slots_shown(&self) -> bool {
+        let at_least_one = self.chapter_view == ChapterView::AtLeastOne;
+        self.show_empty && self.view != GiftView::Bounty && !at_least_one
+    }
+
+    fn single_rank_chart(&self, color:Color32,rank:usize, average_ranks:&[AverageRank], base:&[f64], name:&str) -> BarSeries {
+        let bars = self.karma_range.iter().enumerate().map(|(i,karma)| {
+            plt::Bar::new(self.x_value(*karma), average_ranks[i][rank])
+                .base_offset(base[i])
+                .width(self.bar_width())
+                .fill(color)
+        }).collect();
+        BarSeries { name: name.to_string(), color, bars }
+    }
+
+}
+
+// ----------------------- DONUT CHART -------------------------
+
+/// Radius of the hole in the middle of the donut, the outer radius is 1
+const DONUT_INNER_RADIUS:f64 = 0.55;
+/// Largest angle of a single piece of a slice in radians, so every piece is convex
+const DONUT_PIECE_ANGLE:f64 = 0.05;
+/// Slices smaller than this part of the slots do not get a label
+const DONUT_LABEL_FRACTION:f64 = 0.03;
+
+impl PlotProgram {
+    /// Composition of the gift slots of a single monster at the cursor karma, by gift type and exact rank.
+    /// Bounty gifts do not take a slot, so they are not part of the donut
+    fn donut_slices(&mut self) -> Vec<DonutSlice> {
+        let (gift_ranks, empty_slots) = self.sequence(self.cursor.karma, self.wonderful_count);
+        let mut amounts = Vec::new();
+        for (gift_type, _, name) in self.stacked_gifts() {
+            for (rank, amount) in exact_ranks(&gift_ranks[gift_type]).into_iter().enumerate() {
+                amounts.push((format!("{name} {} star", rank + 1), gift_color(gift_type, rank), amount));
+            }
+        }
+        amounts.push(("empty slots".to_string(), Color32::from_hex(EMPTY_COLOR).unwrap(), empty_slots));
+
+        let total:f64 = amounts.iter().map(|(_, _, amount)| amount).sum();
+        let point = |angle:f64, radius:f64| [radius * angle.sin(), radius * angle.cos()];
+        //slices start at the top and go clockwise
+        let mut start = 0.0;
+        let mut slices = Vec::new();
+        for (name, color, amount) in amounts {
+            if amount <= 0.0 {
+                continue;
+            }
+            let angle = std::f64::consts::TAU * amount / total;
+            let piece_count = (angle / DONUT_PIECE_ANGLE).ceil().max(1.0) as usize;
+            let pieces = (0..piece_count).map(|i| {
+                let from = start + angle * i as f64 / piece_count as f64;
+                let to = start + angle * (i + 1) as f64 / piece_count as f64;
+                vec![point(from, DONUT_INNER_RADIUS), point(from, 1.0), point(to, 1.0), point(to, DONUT_INNER_RADIUS)]
+            }).collect();
+            let label = point(start + angle / 2.0, (1.0 + DONUT_INNER_RADIUS) / 2.0);
+            slices.push(DonutSlice { name, color, amount, pieces, label });
+            start += angle;
+        }
+        slices
+    }
+}
+
+
+// ----------------------- USER INTERACTION -------------------------
+
+/// Width of every column in the data table, the header and the rows are separate grids so they need the same width
+const TABLE_COLUMN_WIDTH:f32 = 72.0;
+
+impl PlotProgram {
+    /// The numbers behind the chart, for the visible gifts
+    fn table_panel(&mut self, ctx:&egui::Context) {
+        let mut clicked = None;
+        let mut copy = false;
+        let mut filtered = false;
+        egui::SidePanel::right("data_table").show(ctx, |ui| {
+            let table = &mut self.table;
+            ui.heading("data");
+            ui.horizontal(|ui| {
+                filtered |= ui.add(egui::TextEdit::singleline(&mut table.column_filter).hint_text("columns").desired_width(100.0)).changed();
+                copy = ui.button("copy as tsv").clicked();
+            });
+            ui.horizontal(|ui| {
+                filtered |= ui.checkbox(&mut table.karma_filter, "from").changed();
+                filtered |= ui.add_enabled(table.karma_filter, egui::DragValue::new(&mut table.from).speed(0.01).max_decimals(4)).changed();
+                ui.label("to");
+                filtered |= ui.add_enabled(table.karma_filter, egui::DragValue::new(&mut table.to).speed(0.01).max_decimals(4)).changed();
+            });
+            let (columns, rows) = (&table.data, &table.rows);
+            ui.separator();
+
+            let row_height = ui.spacing().interact_size.y;
+            egui::ScrollArea::horizontal().show(ui, |ui| {
+                ui.vertical(|ui| {
+                    egui::Grid::new("table_header").show(ui, |ui| {
+                        for (i, column) in columns.iter().enumerate() {
+                            let arrow = match table.sort {
+                                Some((sorted, false)) if sorted == i => " ^",
+                                Some((sorted, true)) if sorted == i => " v",
+                                _ => ""
+                            };
+                            let button = egui::Button::new(format!("{}{arrow}", column.name)).small();
+                            if ui.add_sized([TABLE_COLUMN_WIDTH, row_height], button).clicked() {
+                                clicked = Some(i);
+                            }
+                        }
+                        ui.end_row();
+                    });
+                    egui::ScrollArea::vertical().show_rows(ui, row_height, rows.len(), |ui, visible| {
+                        egui::Grid::new("table_rows").show(ui, |ui| {
+                            for row in &rows[visible] {
+                                for column in columns {
+                                    ui.add_sized([TABLE_COLUMN_WIDTH, row_height], egui::Label::new(format!("{:.4}", column.values[*row])));
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+                });
+            });
+        });
+
+        if let Some(column) = clicked {
+            self.table.click_header(column);
+            filtered = true;
+        }
+        if filtered {
+            self.rebuild_table();
+        }
+        if copy {
+            let text = table::tsv(&self.table.data, &self.table.rows);
+            ctx.output_mut(|output| output.copied_text = text);
+        }
+    }
+
+    /// Every gift at the cursor karma, with the probabilities they are calculated from
+    fn breakdown_panel(&mut self, ctx:&egui::Context) {
+        let karma = self.cursor.karma;
+        let rules = self.profile.rules;
+        let mut moved = None;
+        //chances of at least one gift can not be added up
+        let at_least_one = self.chapter_view == ChapterView::AtLeastOne;
+
+        egui::SidePanel::right("breakdown").show(ctx, |ui| {
+            ui.heading("breakdown");
+            let scale = self.domain_settings.unit.scale();
+            let mut x = karma * scale;
+            if ui.add(egui::DragValue::new(&mut x)
+                .clamp_range(self.domain_settings.min * scale..=self.domain_settings.max * scale)
+                .speed(0.01 * scale)
+                .max_decimals(4)
+                .prefix(format!("{}: ", self.domain_settings.unit.axis_label()))).changed() {
+                    moved = Some(x / scale);
+            }
+            ui.separator();
+
+            let CursorValues { gift_types, gift_ranks, empty_slots, probabilities, .. } = &self.cursor.values;
+            egui::Grid::new("breakdown_gifts").striped(true).show(ui, |ui| {
+                ui.label("gift");
+                for rank in 0..rules.ranks {
+                    ui.label(if rank == 0 {"1+ star".to_string()} else {rules.rank_name(rank)});
+                }
+                ui.end_row();
+                let mut total:AverageRank = rules.no_ranks();
+                for gift_type in gift_types {
+                    let ranks = &gift_ranks[*gift_type];
+                    ui.label(gift_type.name());
+                    for (rank, total) in ranks.iter().zip(total.iter_mut()) {
+                        ui.label(format!("{rank:.4}"));
+                        *total += rank;
+                    }
+                    ui.end_row();
+                }
+                if !at_least_one {
+                    ui.label("total");
+                    for rank in total {
+                        ui.label(format!("{rank:.4}"));
+                    }
+                    ui.end_row();
+                    ui.label("empty slots");
+                    ui.label(format!("{empty_slots:.4}"));
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+
+            ui.label("probabilities");
+            egui::Grid::new("breakdown_probabilities").striped(true).show(ui, |ui| {
+                ui.label("gift");
+                for attempt in 1..=rules.max_tries {
+                    ui.label(format!("chosen try {attempt}"));
+                }
+                for rank in 2..=rules.ranks {
+                    ui.label(format!("rank up {rank}"));
+                }
+                ui.end_row();
+                for (gift_type, prob) in probabilities {
+                    ui.label(gift_type.name());
+                    for chance in prob.chosen.iter().chain(&prob.rank_up) {
+                        ui.label(format!("{chance:.4}"));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        if let Some(karma) = moved {
+            self.move_cursor(karma);
+        }
+    }
+
+    /// Slot gifts and bounty gifts of every chapter side by side, all slot charts and all bounty charts have the same axes
+    fn dashboard_grid(&self, ui:&mut egui::Ui) {
+        let charts = &self.charts.dashboard;
+        let slot_top = charts.iter().fold(0.0, |top:f64, chart| top.max(chart.slot_top));
+        let bounty_top = charts.iter().fold(0.0, |top:f64, chart| top.max(chart.bounty_top));
+
+        //one legend for all charts, with the colors of the 1, 2 and 3 star gifts
+        ui.horizontal_wrapped(|ui| {
+            let mut gift_types:Vec<GType> = GType::ALL.into_iter()
+                .filter(|gift_type| !gift_type.info().takes_slot || Chapter::ALL.iter().any(|chapter| self.stack_types(*chapter).contains(gift_type)))
+                .collect();
+            gift_types.sort_by_key(|gift_type| !gift_type.info().takes_slot);
+            for gift_type in gift_types {
+                for rank in 0..self.profile.rules.ranks {
+                    ui.label(egui::RichText::new("■").color(gift_color(gift_type, rank)));
+                }
+                ui.label(gift_type.name());
+            }
+            if self.empty_slots_shown() {
+                ui.label(egui::RichText::new("■").color(Color32::from_hex(EMPTY_COLOR).unwrap()));
+                ui.label("empty slots");
+            }
+        });
+
+        ui.columns(charts.len().max(1), |columns| {
+            for (ui, chart) in columns.iter_mut().zip(charts) {
+                ui.label(chart.chapter.name());
+                for (kind, series, top, aspect) in [("slots", &chart.slots, slot_top, 1.2), ("bounty", &chart.bounty, bounty_top, 2.0)] {
+                    egui_plot::Plot::new(format!("dashboard_{kind}_{}", chart.chapter.name()))
+                        .view_aspect(aspect)
+                        .allow_drag(false)
+                        .allow_scroll(false)
+                        .allow_zoom(false)
+                        .include_y(0.0)
+                        .include_y(top)
+                        .link_cursor("dashboard", true, false)
+                        .show(ui, |plot_ui| {
+                            for series in series {
+                                plot_ui.bar_chart(series.chart());
+                            }
+                        });
+                }
+            }
+        });
+    }
+
+    /// List of saved scenarios, clicking one replaces the current settings
+    fn scenario_panel(&mut self, ctx:&egui::Context) {
+        let mut selected = None;
+        let mut save = false;
+        let mut copy = false;
+        egui::SidePanel::left("scenarios").show(ctx, |ui| {
+            ui.heading("scenarios");
+            for scenario in &self.scenarios.saved {
+                if ui.selectable_label(scenario.name == self.scenarios.name, &scenario.name).clicked() {
+                    selected = Some(scenario.clone());
+                }
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.scenarios.name).hint_text("name").desired_width(120.0));
+                save = ui.button("save").clicked();
+            });
+            ui.separator();
+            //codes to share the current scenario without files
+            copy = ui.button("copy code").clicked();
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.scenarios.code).hint_text("scenario code").desired_width(120.0));
+                if ui.button("open").clicked() {
+                    match Scenario::from_code(&self.scenarios.code) {
+                        Ok(scenario) => selected = Some(scenario),
+                        Err(error) => self.scenarios.status = error
+                    }
+                }
+            });
+            if !self.scenarios.status.is_empty() {
+                ui.label(&self.scenarios.status);
+            }
+        });
+
+        if copy {
+            let code = Scenario::of(self, "").code();
+            ctx.output_mut(|output| output.copied_text = code);
+            self.scenarios.status = "code copied".to_string();
+        }
+
+        if let Some(scenario) = selected {
+            match scenario.apply(self) {
+                Ok(()) => {
+                    self.scenarios.status = if scenario.name.is_empty() {
+                        "code opened".to_string()
+                    } else {
+                        format!("{} loaded", scenario.name)
+                    };
+                    self.scenarios.name = scenario.name;
+                    self.recalc();
+                },
+                Err(error) => self.scenarios.status = error
+            }
+        }
+        if save {
+            let name = self.scenarios.name.trim().to_string();
+            self.scenarios.status = if name.is_empty() {
+                "a scenario needs a name".to_string()
+            } else {
+                match Scenario::of(self, &name).save() {
+                    Ok(path) => format!("saved to {}", path.display()),
+                    Err(error) => error
+                }
+            };
+            self.scenarios.refresh();
+        }
+    }
+}
+
+
+impl eframe::App for PlotProgram {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.scenarios.open {
+            self.scenario_panel(ctx);
+        }
+        if self.table.open {
+            self.table_panel(ctx);
+        }
+        if self.cursor.show {
+            self.breakdown_panel(ctx);
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            //setting buttons
+            let mut recalc = false;
+            let rules = self.profile.rules;
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Chapter")
+                    .selected_text(self.chapter.name())
+                    .show_ui(ui, |ui| {
+                        for chapter in Chapter::ALL {
+                            if ui.selectable_value(&mut self.chapter, chapter, chapter.name()).clicked() {recalc = true};
+                        }
+                    });
+                ui.label("|");
+                if ui.selectable_value(&mut self.view, GiftView::Slots, "slot gifts").clicked() {recalc = true};
+                if ui.selectable_value(&mut self.view, GiftView::Bounty, "bounty gifts").clicked() {recalc = true};
+                if ui.selectable_value(&mut self.view, GiftView::Combined, "combined").clicked() {recalc = true};
+                if ui.selectable_value(&mut self.view, GiftView::PerSlot, "per slot").clicked() {recalc = true};
+                if ui.selectable_value(&mut self.view, GiftView::Dashboard, "all chapters").clicked() {recalc = true};
+                ui.label("|");
+                if ui.toggle_value(&mut self.scenarios.open, "scenarios").clicked() {
+                    self.scenarios.refresh();
+                }
+                if ui.toggle_value(&mut self.table.open, "table").clicked() && !self.table.karma_filter {
+                    self.table.from = self.x_value(self.domain_settings.min);
+                    self.table.to = self.x_value(self.domain_settings.max);
+                }
+                let cursor = ui.toggle_value(&mut self.cursor.show, "cursor").clicked();
+                let donut = ui.toggle_value(&mut self.show_donut, "donut").clicked();
+                //the donut is moved with the cursor
+                if donut && self.show_donut {
+                    self.cursor.show = true;
+                }
+                if cursor || donut {
+                    let settings = &self.domain_settings;
+                    if !(settings.min..=settings.max).contains(&self.cursor.karma) {
+                        self.cursor.karma = (settings.min + settings.max) / 2.0;
+                    }
+                    self.rebuild_cursor();
+                }
+            });
+            
+
+            ui.horizontal(|ui| {
+                let settings = &mut self.domain_settings;
+                
+                if ui.add(egui::DragValue::new(&mut settings.min)
+                    .clamp_range(0.0..=settings.max)
+                    .speed(0.01)
+                    .max_decimals(4)
+                    .prefix("min: ")).changed() {
+                        recalc = true;
+                }
+                if ui.add(egui::DragValue::new(&mut settings.max)
+                    .clamp_range(settings.min..=settings.limit)
+                    .speed(0.01)
+                    .max_decimals(4)
+                    .prefix("max: ")).changed() {
+                        recalc = true
+                }
+                //the step is limited so the amount of karma points stays reasonable
+                let min_step = f64::max(MIN_KARMA_STEP, (settings.max - settings.min) / MAX_KARMA_POINTS);
+                let max_step = f64::max(min_step, settings.max - settings.min);
+                if ui.add(egui::DragValue::new(&mut settings.step)
+                    .clamp_range(min_step..=max_step)
+                    .speed(0.001)
+                    .max_decimals(4)
+                    .prefix("step: ")).changed() {
+                        recalc = true
+                }
+                if ui.add(egui::DragValue::new(&mut self.wonderful_count)
+                    .clamp_range(0..=MAX_WONDERFUL_COUNT)
+                    .speed(1.0)
+                    .prefix("gifts: ")).changed() {
+                        recalc = true
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let settings = &mut self.domain_settings;
+                if ui.add(egui::DragValue::new(&mut settings.limit)
+                    .clamp_range(0.0..=MAX_KARMA_LIMIT)
+                    .speed(0.1)
+                    .prefix("limit: ")).changed() {
+                        settings.max = settings.max.min(settings.limit);
+                        settings.min = settings.min.min(settings.max);
+                        recalc = true
+                }
+                ui.label("|");
+                if ui.selectable_value(&mut settings.unit, KarmaUnit::Raw, "karma").clicked() {recalc = true};
+                if ui.selectable_value(&mut settings.unit, KarmaUnit::Percent, "percent").clicked() {recalc = true};
+                ui.label("|");
+                if ui.checkbox(&mut self.show_empty, "empty slots").changed() {recalc = true};
+                ui.label("|");
+                if ui.checkbox(&mut self.show_spread, "spread").changed() {recalc = true};
+                if self.show_spread {
+                    if ui.selectable_value(&mut self.spread_rank, 0, "all").clicked() {recalc = true};
+                    for rank in 1..rules.ranks {
+                        if ui.selectable_value(&mut self.spread_rank, rank, rules.rank_name(rank)).clicked() {recalc = true};
+                    }
+                }
+                ui.label("|");
+                if ui.checkbox(&mut self.heatmap.show, "heatmap").changed() {recalc = true};
+                if self.heatmap.show {
+                    egui::ComboBox::from_id_source("heatmap_gift")
+                        .selected_text(self.heatmap.gift_type.name())
+                        .show_ui(ui, |ui| {
+                            for gift_type in GType::ALL {
+                                if ui.selectable_value(&mut self.heatmap.gift_type, gift_type, gift_type.name()).clicked() {recalc = true};
+                            }
+                        });
+                    if ui.selectable_value(&mut self.heatmap.rank, 0, "all").clicked() {recalc = true};
+                    for rank in 1..rules.ranks {
+                        if ui.selectable_value(&mut self.heatmap.rank, rank, rules.rank_name(rank)).clicked() {recalc = true};
+                    }
+                }
+            });
+
+            //monsters per chapter, alter story has a known range of boss fights
+            ui.horizontal(|ui| {
+                let monsters = self.monsters.of_mut(self.chapter);
+                if self.chapter.model().boss_fights() {
+                    egui::ComboBox::from_label("fights")
+                        .selected_text(match monsters {
+                            MonsterCount::Fixed(count) => format!("{count}"),
+                            MonsterCount::Distribution(_) => format!("{MIN_BOSS_FIGHTS}-{MAX_BOSS_FIGHTS}")
+                        })
+                        .show_ui(ui, |ui| {
+                            for count in MIN_BOSS_FIGHTS..=MAX_BOSS_FIGHTS {
+                                if ui.selectable_value(monsters, MonsterCount::Fixed(count), format!("{count}")).clicked() {recalc = true};
+                            }
+                            if ui.selectable_label(matches!(monsters, MonsterCount::Distribution(_)), format!("{MIN_BOSS_FIGHTS}-{MAX_BOSS_FIGHTS}")).clicked() {
+                                *monsters = MonsterCount::Distribution([0.25;4]);
+                                recalc = true;
+                            }
+                        });
+                } else if let MonsterCount::Fixed(count) = monsters {
+                    if ui.add(egui::DragValue::new(count)
+                        .clamp_range(1..=MAX_MONSTERS)
+                        .speed(0.2)
+                        .prefix("monsters: ")).changed() {
+                            recalc = true
+                    }
+                }
+                if let MonsterCount::Distribution(weights) = monsters {
+                    for (i, weight) in weights.iter_mut().enumerate() {
+                        if ui.add(egui::DragValue::new(weight)
+                            .clamp_range(0.0..=1.0)
+                            .speed(0.01)
+                            .prefix(format!("{}: ", i + MIN_BOSS_FIGHTS))).changed() {
+                                recalc = true
+                        }
+                    }
+                }
+                ui.label("|");
+                egui::ComboBox::from_label("view")
+                    .selected_text(match self.chapter_view {
+                        ChapterView::PerMonster => "per monster",
+                        ChapterView::ChapterTotal => "chapter total",
+                        ChapterView::AtLeastOne => "at least one"
+                    })
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_value(&mut self.chapter_view, ChapterView::PerMonster, "per monster").clicked() {recalc = true};
+                        if ui.selectable_value(&mut self.chapter_view, ChapterView::ChapterTotal, "chapter total").clicked() {recalc = true};
+                        if ui.selectable_value(&mut self.chapter_view, ChapterView::AtLeastOne, "at least one").clicked() {recalc = true};
+                    });
+            });
+
+            //tower burden rules are estimates, so they can be tuned
+            egui::CollapsingHeader::new("model assumptions").show(ui, |ui| {
+                let profile = &mut self.profile;
+                //hypothetical or modded rules, new tries and rank ups get formulas that can be set in a profile
+                ui.horizontal(|ui| {
+                    let mut rules = profile.rules;
+                    let mut changed = false;
+                    for (value, name) in [(&mut rules.ranks, "ranks"), (&mut rules.slots, "slots"), (&mut rules.max_tries, "tries per gift")] {
+                        changed |= ui.add(egui::DragValue::new(value)
+                            .clamp_range(MIN_RULE_VALUE..=MAX_RULE_VALUE)
+                            .prefix(format!("{name}: ")))
+                            .on_hover_text(format!("{MIN_RULE_VALUE} to {MAX_RULE_VALUE}, the towers place gifts of up to 3 stars in the first 3 slots so the rules can not be reduced"))
+                            .changed();
+                    }
+                    if changed {
+                        profile.set_rules(rules);
+                        recalc = true;
+                    }
+                });
+                if ui.add(egui::Slider::new(&mut profile.burden_rank_down, 0.0..=1.0)
+                    .text("tower burden one rank lower")).changed() {
+                        recalc = true
+                }
+                if ui.add(egui::Slider::new(&mut profile.burden_three_star, 0.0..=1.0)
+                    .text("tower 3 star burden if only first blessing is 3 star")).changed() {
+                        recalc = true
+                }
+                if ui.add(egui::Slider::new(&mut profile.special_burden_rank2, 0.0..=1.0)
+                    .text("special tower burden chance for 2 star power")).changed() {
+                        recalc = true
+                }
+                if ui.add(egui::Slider::new(&mut profile.special_burden_rank3, 0.0..=1.0)
+                    .text("special tower burden chance for 3 star power")).changed() {
+                        recalc = true
+                }
+                if ui.button("reset").clicked() {
+                    *profile = ModelProfile::default();
+                    recalc = true;
+                }
+
+                //orders in which the gift types are tried, empty for the default orders
+                ui.horizontal(|ui| {
+                    ui.label("orders:");
+                    if ui.add(egui::TextEdit::singleline(&mut self.orders_text)
+                        .hint_text(orders_text(&default_orders(self.chapter)))).lost_focus() {
+                            recalc = true
+                    }
+                });
+                if !self.orders_status.is_empty() {
+                    ui.label(&self.orders_status);
+                }
+
+                //fitted profiles include the formulas as well
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.profile_path).hint_text("profile (.json)"));
+                    if ui.button("load").clicked() {
+                        self.load_profile();
+                        recalc = true;
+                    }
+                    if ui.button("save").clicked() {
+                        self.profile_status = match self.profile.save(&self.profile_path) {
+                            Ok(()) => "profile saved".to_string(),
+                            Err(error) => error
+                        };
+                    }
+                });
+                if !self.profile_status.is_empty() {
+                    ui.label(&self.profile_status);
+                }
+            });
+            //gift logs from play sessions
+            egui::CollapsingHeader::new("observations").show(ui, |ui| {
+                let settings = &mut self.observation_settings;
+                let mut load = false;
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut settings.path).hint_text("gift log (.csv or .json)"));
+                    load = ui.button("load").clicked();
+                });
+                if !settings.status.is_empty() {
+                    ui.label(&settings.status);
+                }
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut settings.show, "show").changed() {recalc = true};
+                    if ui.add(egui::DragValue::new(&mut settings.bin_width)
+                        .clamp_range(0.01..=1.0)
+                        .speed(0.01)
+                        .prefix("bin width: ")).changed() {
+                            recalc = true
+                    }
+                    ui.label("|");
+                    if ui.selectable_value(&mut settings.interval, IntervalMethod::Wilson, "Wilson").clicked() {recalc = true};
+                    if ui.selectable_value(&mut settings.interval, IntervalMethod::Bootstrap, "Bootstrap").clicked() {recalc = true};
+                });
+                if load {
+                    self.load_observations();
+                    recalc = true;
+                }
+            });
+            //value of every gift for a build, to find the best karma
+            egui::CollapsingHeader::new("utility").show(ui, |ui| {
+                let settings = &mut self.utility;
+                let ranks = self.profile.rules.ranks;
+                if ui.checkbox(&mut settings.show, "show").changed() {recalc = true};
+                egui::Grid::new("utility_weights").show(ui, |ui| {
+                    ui.label("");
+                    for rank in 1..=ranks {
+                        ui.label(format!("{rank} star"));
+                    }
+                    ui.end_row();
+                    for gift_type in GType::ALL {
+                        ui.label(gift_type.name());
+                        for value in settings.weights.values[gift_type].iter_mut().take(ranks) {
+                            if ui.add(egui::DragValue::new(value).speed(0.1).max_decimals(2)).changed() {recalc = true};
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let mut selected = None;
+                    egui::ComboBox::from_id_source("builds")
+                        .selected_text("builds")
+                        .show_ui(ui, |ui| {
+                            for (name, weights) in &settings.builds {
+                                if ui.selectable_label(*name == settings.build, name).clicked() {
+                                    selected = Some((name.clone(), weights.clone()));
+                                }
+                            }
+                        });
+                    if let Some((name, weights)) = selected {
+                        settings.build = name;
+                        settings.weights = weights;
+                        recalc = true;
+                    }
+                    ui.add(egui::TextEdit::singleline(&mut settings.build).hint_text("build").desired_width(100.0));
+                    if ui.button("save").clicked() {
+                        settings.save();
+                    }
+                    if ui.button("delete").clicked() {
+                        settings.delete();
+                    }
+                    if ui.button("reset").clicked() {
+                        settings.weights = Default::default();
+                        recalc = true;
+                    }
+                });
+                if !settings.status.is_empty() {
+                    ui.label(&settings.status);
+                }
+                if let Some((line, optimum)) = &self.charts.utility {
+                    let [best_x, best_utility] = line.points[optimum.best];
+                    let flat_x = line.points[optimum.flat][0];
+                    ui.label(format!("best karma {best_x:.3} with utility {best_utility:.3}, {:.0}% of the gain at {flat_x:.3}", FLAT_FRACTION * 100.0));
+                }
+            });
+            if recalc {self.recalc()}
+
+            //slots only hold gifts of a single monster, so the boss fight view does not apply
+            if self.view == GiftView::PerSlot {
+                for (slot, bars) in self.charts.slots.iter().enumerate() {
+                    egui_plot::Plot::new(format!("slot_{slot}"))
+                        .view_aspect(3.0)
+                        .allow_drag(false)
+                        .allow_scroll(false)
+                        .allow_zoom(false)
+                        .include_y(1.0)
+                        .x_axis_label(self.domain_settings.unit.axis_label())
+                        .y_axis_label(format!("slot {} filled", slot + 1))
+                        .legend(plt::Legend::default().position(plt::Corner::LeftTop))
+                        .show(ui, |plot_ui| {
+                            for series in bars {
+                                plot_ui.bar_chart(series.chart());
+                            }
+                        });
+                }
+            } else if self.view == GiftView::Dashboard {
+                self.dashboard_grid(ui);
+            } else {
+                let y_label = match self.chapter_view {
+                    ChapterView::ChapterTotal => "average amount of gifts per chapter",
+                    ChapterView::AtLeastOne => "chance of at least one gift per chapter",
+                    _ => "average amount of gifts"
+                };
+
+                let cursor_x = self.cursor.show.then(|| self.x_value(self.cursor.karma));
+                let dragged_x = egui_plot::Plot::new("my_plot")
+                    .view_aspect(2.0)
+                    .allow_drag(false)
+                    .allow_scroll(false)
+                    .allow_zoom(false)
+                    .x_axis_label(self.domain_settings.unit.axis_label())
+                    .y_axis_label(y_label)
+                    .legend(plt::Legend::default().position(plt::Corner::LeftTop))
+                    .show(ui, |plot_ui| {
+                        for series in &self.charts.bars {
+                            plot_ui.bar_chart(series.chart());
+                        }
+                        if let Some(line) = &self.charts.slot_total {
+                            plot_ui.line(line.line());
+                        }
+                        for series in &self.charts.spread {
+                            plot_ui.box_plot(series.box_plot());
+                        }
+                        for series in &self.charts.observed {
+                            plot_ui.box_plot(series.box_plot());
+                        }
+                        //the cursor follows the pointer while the plot is clicked or dragged
+                        let x = cursor_x?;
+                        plot_ui.vline(plt::VLine::new(x).name("cursor").color(Color32::WHITE));
+                        let response = plot_ui.response();
+                        if response.clicked() || response.dragged() {
+                            return plot_ui.pointer_coordinate().map(|point| point.x);
+                        }
+                        None
+                    }).inner;
+                if let Some(x) = dragged_x {
+                    let settings = &self.domain_settings;
+                    self.move_cursor((x / settings.unit.scale()).clamp(settings.min, settings.max));
+                }
+            }
+
+            if self.show_donut {
+                let slices = &self.cursor.values.donut;
+                let total:f64 = slices.iter().map(|slice| slice.amount).sum();
+                let title = format!("{}\n{} {:.3}", self.chapter.name(), self.domain_settings.unit.axis_label(), self.x_value(self.cursor.karma));
+                egui_plot::Plot::new("donut")
+                    .view_aspect(2.0)
+                    .data_aspect(1.0)
+                    .allow_drag(false)
+                    .allow_scroll(false)
+                    .allow_zoom(false)
+                    .show_axes(false)
+                    .show_grid(false)
+                    .show_x(false)
+                    .show_y(false)
+                    .include_x(-1.1)
+                    .include_x(1.1)
+                    .include_y(-1.1)
+                    .include_y(1.1)
+                    .legend(plt::Legend::default().position(plt::Corner::LeftTop))
+                    .show(ui, |plot_ui| {
+                        for slice in slices {
+                            for polygon in slice.polygons() {
+                                plot_ui.polygon(polygon);
+                            }
+                        }
+                        for slice in slices.iter().filter(|slice| slice.amount >= DONUT_LABEL_FRACTION * total) {
+                            let text = egui::RichText::new(format!("{:.2}", slice.amount)).color(Color32::WHITE);
+                            plot_ui.text(plt::Text::new(slice.label.into(), text));
+                        }
+                        plot_ui.text(plt::Text::new([0.0, 0.0].into(), title));
+                    });
+            }
+
+            //every wonderful count at once, the current one is marked with a line
+            if let Some(series) = &self.charts.heatmap {
+                let (min, max) = self.heatmap.range();
+                ui.label(format!("{}: {min:.4} (purple) to {max:.4} (yellow)", series.name));
+                egui_plot::Plot::new("heatmap")
+                    .view_aspect(3.0)
+                    .allow_drag(false)
+                    .allow_scroll(false)
+                    .allow_zoom(false)
+                    .x_axis_label(self.domain_settings.unit.axis_label())
+                    .y_axis_label("wonderful gifts")
+                    .show(ui, |plot_ui| {
+                        plot_ui.bar_chart(series.chart().element_formatter(Box::new(|bar, _| bar.name.clone())));
+                        plot_ui.hline(plt::HLine::new(self.wonderful_count as f64).color(Color32::WHITE));
+                    });
+            }
+
+            if let Some((line, optimum)) = &self.charts.utility {
+                let best_x = line.points[optimum.best][0];
+                let flat_x = line.points[optimum.flat][0];
+                egui_plot::Plot::new("utility")
+                    .view_aspect(3.0)
+                    .allow_drag(false)
+                    .allow_scroll(false)
+                    .allow_zoom(false)
+                    .x_axis_label(self.domain_settings.unit.axis_label())
+                    .y_axis_label("expected utility")
+                    .legend(plt::Legend::default().position(plt::Corner::LeftTop))
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(line.line());
+                        plot_ui.vline(plt::VLine::new(best_x).name("best karma").color(Color32::GREEN));
+                        plot_ui.vline(plt::VLine::new(flat_x)
+                            .name(format!("{:.0}% of the gain", FLAT_FRACTION * 100.0))
+                            .color(Color32::YELLOW)
+                            .style(plt::LineStyle::dashed_loose()));
+                    });
+            }
+
+            //compare the bounty rates of every chapter
+            if let GiftView::Bounty | GiftView::Combined = self.view {
+                egui_plot::Plot::new("bounty_comparison")
+                    .view_aspect(3.0)
+                    .allow_drag(false)
+                    .allow_scroll(false)
+                    .allow_zoom(false)
+                    .x_axis_label(self.domain_settings.unit.axis_label())
+                    .y_axis_label("average bounty gifts")
+                    .legend(plt::Legend::default().position(plt::Corner::LeftTop))
+                    .show(ui, |plot_ui| {
+                        for line in &self.charts.bounty_comparison {
+                            plot_ui.line(line.line());
+                        }
+                    });
+            }
         });
     }
 }
@@ -0,0 +1,92 @@
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive, Zero};
+
+// ------------------- NUMBERS -----------------
+// The sequence engine is written once for every Number. The charts use f64, the exact mode uses rationals
+// so the identities of the model can be checked without rounding errors.
+// The formulas and the profile are stored as f64, every value is read as the shortest decimal that prints it
+
+pub trait Number: Clone + Debug + PartialOrd + Add<Output = Self> + AddAssign + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn from_f64(value:f64) -> Self;
+    fn to_f64(&self) -> f64;
+}
+
+impl Number for f64 {
+    fn zero() -> Self {
+        0.0
+    }
+
+    fn one() -> Self {
+        1.0
+    }
+
+    fn from_f64(value:f64) -> Self {
+        value
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+}
+
+/// Exact rational number with unlimited precision
+pub type Exact = BigRational;
+
+impl Number for Exact {
+    fn zero() -> Self {
+        Zero::zero()
+    }
+
+    fn one() -> Self {
+        One::one()
+    }
+
+    /// f64 prints as the shortest decimal that reads back as the same value, and never in scientific notation
+    fn from_f64(value:f64) -> Self {
+        assert!(value.is_finite(), "{value} can not be an exact number");
+        let text = value.to_string();
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let digits:BigInt = format!("{whole}{fraction}").parse().expect("A finite f64 prints as a decimal");
+        BigRational::new(digits, BigInt::from(10).pow(fraction.len() as u32))
+    }
+
+    fn to_f64(&self) -> f64 {
+        ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+}
+
+/// Sum of all numbers, 0 for none
+pub fn sum<N:Number>(numbers:impl IntoIterator<Item = N>) -> N {
+    numbers.into_iter().fold(N::zero(), |total, number| total + number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratio(numerator:i64, denominator:i64) -> Exact {
+        BigRational::new(BigInt::from(numerator), BigInt::from(denominator))
+    }
+
+    #[test]
+    fn exact_reads_the_shortest_decimal() {
+        assert_eq!(Exact::from_f64(-0.04), ratio(-1, 25));
+        assert_eq!(Exact::from_f64(0.1), ratio(1, 10));
+        assert_eq!(Exact::from_f64(1e-7), ratio(1, 10_000_000));
+        assert_eq!(Exact::from_f64(2.0), ratio(2, 1));
+        assert_eq!(Exact::from_f64(-0.0), ratio(0, 1));
+    }
+
+    #[test]
+    fn exact_converts_back_to_the_same_f64() {
+        for value in [-0.04, 0.1, 1.0 / 3.0, 0.45, 123.456] {
+            assert_eq!(Number::to_f64(&Exact::from_f64(value)), value);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::{Chapter, GType, GameRules, GiftRanks, MAX_RULE_VALUE};
+
+// ------------------- OBSERVED GIFT LOGS -----------------
+
+/// A single monster from a play session together with all gifts it offered
+#[derive(Clone, Debug, Deserialize)]
+pub struct Observation {
+    /// name of the play session the monster was found in
+    #[serde(default)]
+    pub run:String,
+    pub karma:f64,
+    pub chapter:Chapter,
+    pub gifts:Vec<ObservedGift>,
+    /// amount of wonderful power gifts the player already had
+    #[serde(default)]
+    pub wonderful:usize
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct ObservedGift {
+    #[serde(rename = "type")]
+    pub gift_type:GType,
+    /// amount of stars, starting at 1
+    pub rank:usize
+}
+
+/// The way the uncertainty of the observed averages is estimated
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum IntervalMethod {
+    #[default]
+    Wilson,
+    Bootstrap
+}
+
+/// Observed averages of all monsters within a range of karma, with a 95% confidence interval
+#[derive(Clone, Debug)]
+pub struct EmpiricalBin {
+    /// center of the karma range
+    pub karma:f64,
+    pub monsters:usize,
+    pub mean:GiftRanks,
+    pub low:GiftRanks,
+    pub high:GiftRanks
+}
+
+const Z_95:f64 = 1.96;
+const BOOTSTRAP_SAMPLES:usize = 1000;
+
+impl Observation {
+    /// Amount of gifts of each type with at least every rank, in the same layout as the model results.
+    /// Gifts with more stars than the rules allow count as gifts of the highest rank
+    pub fn gift_counts(&self, rules:&GameRules) -> GiftRanks {
+        let mut counts = rules.no_gifts();
+        for gift in &self.gifts {
+            for count in counts[gift.gift_type].iter_mut().take(gift.rank) {
+                *count += 1.0;
+            }
+        }
+        counts
+    }
+}
+
+// ------------------- LOADING -----------------
+
+/// Reads a gift log, files ending in .json are read as json and all other files as csv
+pub fn load_observations(path:&str) -> Result<Vec<Observation>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {path}: {e}"))?;
+    let observations = if path.to_lowercase().ends_with(".json") {
+        serde_json::from_str::<Vec<Observation>>(&text).map_err(|e| format!("Invalid json in {path}: {e}"))?
+    } else {
+        parse_csv(&text)?
+    };
+
+    for observation in &observations {
+        if let Some(gift) = observation.gifts.iter().find(|g| !(1..=MAX_RULE_VALUE).contains(&g.rank)) {
+            return Err(format!("Gift {:?} has rank {}, ranks should be 1 to {MAX_RULE_VALUE}", gift.gift_type, gift.rank));
+        }
+    }
+    Ok(observations)
+}
+
+/// Parses lines of the form `run,karma,chapter,gifts[,wonderful]` where gifts is a space separated list like `power:2 bonus:1`.
+/// Empty lines, lines starting with # and the header line are skipped
+fn parse_csv(text:&str) -> Result<Vec<Observation>, String> {
+    let mut observations = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("run,karma") {
+            continue;
+        }
+        let error = |message:String| format!("line {}: {message}", line_number + 1);
+
+        let fields:Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let (run, karma, chapter, gifts, wonderful) = match fields[..] {
+            [run, karma, chapter, gifts] => (run, karma, chapter, gifts, "0"),
+            [run, karma, chapter, gifts, wonderful] => (run, karma, chapter, gifts, wonderful),
+            _ => return Err(error(format!("expected 4 or 5 fields but found {}", fields.len())))
+        };
+        let karma = karma.parse::<f64>().map_err(|_| error(format!("invalid karma '{karma}'")))?;
+        let chapter = Chapter::from_name(chapter).ok_or_else(|| error(format!("unknown chapter '{chapter}'")))?;
+        let wonderful = wonderful.parse::<usize>().map_err(|_| error(format!("invalid wonderful gift count '{wonderful}'")))?;
+
+        let mut observed_gifts = Vec::new();
+        for gift in gifts.split_whitespace() {
+            let (gift_type, rank) = gift.split_once(':').ok_or_else(|| error(format!("gift '{gift}' should look like type:rank")))?;
+            observed_gifts.push(ObservedGift {
+                gift_type: GType::from_name(gift_type).ok_or_else(|| error(format!("unknown gift type '{gift_type}'")))?,
+                rank: rank.parse().map_err(|_| error(format!("invalid rank '{rank}'")))?
+            });
+        }
+        observations.push(Observation { run: run.to_string(), karma, chapter, gifts: observed_gifts, wonderful });
+    }
+    Ok(observations)
+}
+
+// ------------------- BINNING -----------------
+
+/// Groups the observations of a chapter and wonderful gift count in karma ranges of bin_width and averages the gifts in each range
+pub fn empirical_bins(observations:&[Observation], chapter:Chapter, wonderful_count:usize, bin_width:f64, interval:IntervalMethod, rules:&GameRules) -> Vec<EmpiricalBin> {
+    let mut bins:BTreeMap<i64, Vec<GiftRanks>> = BTreeMap::new();
+    for observation in observations.iter().filter(|o| o.chapter == chapter && o.wonderful == wonderful_count) {
+        let bin = (observation.karma / bin_width).floor() as i64;
+        bins.entry(bin).or_default().push(observation.gift_counts(rules));
+    }
+
+    bins.into_iter().map(|(bin, counts)| {
+        let mean = mean_counts(&counts, rules);
+        let (low, high) = match interval {
+            IntervalMethod::Wilson => wilson_interval(&counts, &mean, rules),
+            IntervalMethod::Bootstrap => bootstrap_interval(&counts, bin as u64, rules)
+        };
+        EmpiricalBin {
+            karma: (bin as f64 + 0.5) * bin_width,
+            monsters: counts.len(),
+            mean,
+            low,
+            high
+        }
+    }).collect()
+}
+
+fn mean_counts(counts:&[GiftRanks], rules:&GameRules) -> GiftRanks {
+    let mut mean = rules.no_gifts();
+    for monster in counts {
+        for (gift_mean, gift_count) in mean.values_mut().zip(monster.values()) {
+            for (rank_mean, rank_count) in gift_mean.iter_mut().zip(gift_count) {
+                *rank_mean += rank_count / counts.len() as f64;
+            }
+        }
+    }
+    mean
+}
+
+/// Most gifts a single monster can offer of one type, used as the amount of trials of the Wilson interval
+fn max_per_monster(gift_type:GType, rules:&GameRules) -> f64 {
+    if gift_type.info().takes_slot {rules.slots as f64} else {1.0}
+}
+
+/// Wilson score interval where every monster counts as one trial per slot it could fill with the gift
+fn wilson_interval(counts:&[GiftRanks], mean:&GiftRanks, rules:&GameRules) -> (GiftRanks, GiftRanks) {
+    let mut low = rules.no_gifts();
+    let mut high = rules.no_gifts();
+    for gift_type in GType::ALL {
+        let max = max_per_monster(gift_type, rules);
+        let trials = counts.len() as f64 * max;
+        for rank in 0..rules.ranks {
+            let (l, h) = wilson(mean[gift_type][rank] / max, trials);
+            low[gift_type][rank] = l * max;
+            high[gift_type][rank] = h * max;
+        }
+    }
+    (low, high)
+}
+
+fn wilson(p:f64, trials:f64) -> (f64, f64) {
+    let z2 = Z_95 * Z_95;
+    let denominator = 1.0 + z2 / trials;
+    let center = (p + z2 / (2.0 * trials)) / denominator;
+    let half_width = Z_95 / denominator * (p * (1.0 - p) / trials + z2 / (4.0 * trials * trials)).sqrt();
+    (center - half_width, center + half_width)
+}
+
+/// Percentile bootstrap, the monsters of the bin are resampled with replacement
+fn bootstrap_interval(counts:&[GiftRanks], seed:u64, rules:&GameRules) -> (GiftRanks, GiftRanks) {
+    let mut rng = XorShift::new(seed);
+    let mut samples = Vec::with_capacity(BOOTSTRAP_SAMPLES);
+    for _ in 0..BOOTSTRAP_SAMPLES {
+        let resampled:Vec<GiftRanks> = (0..counts.len())
+            .map(|_| counts[rng.below(counts.len())].clone())
+            .collect();
+        samples.push(mean_counts(&resampled, rules));
+    }
+
+    let mut low = rules.no_gifts();
+    let mut high = rules.no_gifts();
+    for gift_type in GType::ALL {
+        for rank in 0..rules.ranks {
+            let mut values:Vec<f64> = samples.iter().map(|s| s[gift_type][rank]).collect();
+            values.sort_by(f64::total_cmp);
+            low[gift_type][rank] = values[(BOOTSTRAP_SAMPLES as f64 * 0.025) as usize];
+            high[gift_type][rank] = values[(BOOTSTRAP_SAMPLES as f64 * 0.975) as usize];
+        }
+    }
+    (low, high)
+}
+
+/// Amount of different play sessions in the observations
+pub fn run_count(observations:&[Observation]) -> usize {
+    let runs:std::collections::HashSet<&str> = observations.iter().map(|o| o.run.as_str()).collect();
+    runs.len()
+}
+
+/// Small deterministic random number generator, so the same log always gives the same intervals
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed:u64) -> Self {
+        XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Random number from 0 up to (not including) max
+    pub fn below(&mut self, max:usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_with_and_without_wonderful_column() {
+        let text = "run,karma,chapter,gifts,wonderful\n# comment\n\nr1,0.5,Story,power:1 power:3 bounty:2\nr2, 1.25 ,alter story,quick:2,2\n";
+        let observations = parse_csv(text).unwrap();
+        assert_eq!(observations.len(), 2);
+
+        let first = &observations[0];
+        assert_eq!((first.run.as_str(), first.karma, first.chapter, first.wonderful), ("r1", 0.5, Chapter::Story, 0));
+        let gifts:Vec<(GType, usize)> = first.gifts.iter().map(|gift| (gift.gift_type, gift.rank)).collect();
+        assert_eq!(gifts, [(GType::Power, 1), (GType::Power, 3), (GType::Bounty, 2)]);
+
+        let second = &observations[1];
+        assert_eq!((second.run.as_str(), second.karma, second.chapter, second.wonderful), ("r2", 1.25, Chapter::AStory, 2));
+        assert_eq!(second.gifts.len(), 1);
+    }
+
+    #[test]
+    fn csv_errors_name_the_line() {
+        let error = |text:&str| parse_csv(text).unwrap_err();
+        assert_eq!(error("r1,0.5,Story"), "line 1: expected 4 or 5 fields but found 3");
+        assert_eq!(error("r1,0.5,Story,power:1,0,extra"), "line 1: expected 4 or 5 fields but found 6");
+        assert_eq!(error("run,karma,chapter,gifts\nr1,high,Story,power:1"), "line 2: invalid karma 'high'");
+        assert_eq!(error("r1,0.5,Moon,power:1"), "line 1: unknown chapter 'Moon'");
+        assert_eq!(error("r1,0.5,Story,power:1,many"), "line 1: invalid wonderful gift count 'many'");
+        assert_eq!(error("r1,0.5,Story,power"), "line 1: gift 'power' should look like type:rank");
+        assert_eq!(error("r1,0.5,Story,magic:1"), "line 1: unknown gift type 'magic'");
+        assert_eq!(error("r1,0.5,Story,power:x"), "line 1: invalid rank 'x'");
+    }
+
+    #[test]
+    fn wilson_interval_of_half() {
+        //50 out of 100 has the interval 0.4038 to 0.5962
+        let (low, high) = wilson(0.5, 100.0);
+        assert!((low - 0.4038).abs() < 1e-4 && (high - 0.5962).abs() < 1e-4, "{low} to {high}");
+        let (low, high) = wilson(0.0, 10.0);
+        assert!(low.abs() < 1e-12 && (high - 0.2775).abs() < 1e-4, "{low} to {high}");
+    }
+
+    #[test]
+    fn bootstrap_interval_contains_the_mean() {
+        let rules = GameRules::default();
+        let monster = |gifts:Vec<ObservedGift>| Observation { run: String::new(), karma: 0.0, chapter: Chapter::Story, gifts, wonderful: 0 };
+        let power = ObservedGift { gift_type: GType::Power, rank: 2 };
+        let counts:Vec<GiftRanks> = (0..40).map(|i| monster(vec![power; i % 3]).gift_counts(&rules)).collect();
+        let mean = mean_counts(&counts, &rules);
+        let (low, high) = bootstrap_interval(&counts, 1, &rules);
+        for rank in 0..rules.ranks {
+            assert!(low[GType::Power][rank] <= mean[GType::Power][rank] && mean[GType::Power][rank] <= high[GType::Power][rank]);
+        }
+        assert!(low[GType::Power][0] < high[GType::Power][0]);
+        //gifts that no monster offered have no spread
+        assert_eq!((low[GType::Bonus][0], high[GType::Bonus][0]), (0.0, 0.0));
+        assert_eq!(bootstrap_interval(&counts, 1, &rules), (low, high));
+    }
+}
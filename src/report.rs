@@ -0,0 +1,443 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::likelihood::{self, Group};
+use crate::observations::{self, Observation};
+use crate::{chapter_sequence, Chapter, GameRules, ModelProfile, USAGE};
+
+// ------------------- REPORT CONTENTS -----------------
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub chapters:Vec<ChapterReport>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChapterReport {
+    pub chapter:String,
+    pub monsters:usize,
+    pub log_likelihood:f64,
+    /// tests of all karma bins together
+    pub total:FitTest,
+    pub bins:Vec<BinReport>,
+    pub runs:Vec<RunScore>,
+    /// cells with the largest pearson residuals, largest first
+    pub residuals:Vec<Residual>
+}
+
+/// Pearson chi-square and likelihood-ratio (G) test of observed against expected cell counts
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FitTest {
+    pub chi_square:f64,
+    pub likelihood_ratio:f64,
+    pub degrees_of_freedom:usize,
+    pub chi_square_p:f64,
+    pub likelihood_ratio_p:f64,
+    /// cells that were observed although the model says they are impossible, both p values are 0 when there are any
+    pub impossible_cells:usize
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BinReport {
+    /// center of the karma bin
+    pub karma:f64,
+    pub monsters:usize,
+    pub test:FitTest
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RunScore {
+    pub run:String,
+    pub monsters:usize,
+    pub log_likelihood:f64,
+    pub per_monster:f64
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Residual {
+    pub cell:String,
+    pub karma:f64,
+    pub observed:f64,
+    pub expected:f64,
+    /// for impossible cells the expected amount is floored at the lowest chance of the likelihood
+    pub residual:f64,
+    /// the cell was observed although the model says it is impossible
+    pub impossible:bool
+}
+
+// ------------------- CALCULATIONS -----------------
+
+pub fn report(observations:&[Observation], profile:&ModelProfile, bin_width:f64, residual_count:usize) -> Report {
+    let chapters = Chapter::ALL.into_iter()
+        .filter(|chapter| observations.iter().any(|o| o.chapter == *chapter))
+        .map(|chapter| chapter_report(observations, chapter, profile, bin_width, residual_count))
+        .collect();
+    Report { chapters }
+}
+
+fn chapter_report(observations:&[Observation], chapter:Chapter, profile:&ModelProfile, bin_width:f64, residual_count:usize) -> ChapterReport {
+    let chapter_observations:Vec<&Observation> = observations.iter().filter(|o| o.chapter == chapter).collect();
+
+    //observed and expected cells per karma bin
+    let mut bins:BTreeMap<i64, Vec<&Observation>> = BTreeMap::new();
+    for observation in &chapter_observations {
+        bins.entry((observation.karma / bin_width).floor() as i64).or_default().push(observation);
+    }
+
+    let mut total = CellTotals::default();
+    let mut bin_reports = Vec::new();
+    let mut residuals = Vec::new();
+    for (bin, bin_observations) in bins {
+        let karma = (bin as f64 + 0.5) * bin_width;
+        let cells = CellTotals::of(&likelihood::group_observations(bin_observations.iter().copied(), chapter, &profile.rules), chapter, profile);
+        residuals.extend(cells.residuals(karma, &profile.rules));
+        bin_reports.push(BinReport { karma, monsters: bin_observations.len(), test: cells.test() });
+        total.add(&cells);
+    }
+    residuals.sort_by(|x, y| y.residual.abs().total_cmp(&x.residual.abs()));
+    residuals.truncate(residual_count);
+
+    //log-likelihood of every play session
+    let mut runs:BTreeMap<&str, Vec<&Observation>> = BTreeMap::new();
+    for observation in &chapter_observations {
+        runs.entry(observation.run.as_str()).or_default().push(observation);
+    }
+    let runs = runs.into_iter().map(|(run, run_observations)| {
+        let groups = likelihood::group_observations(run_observations.iter().copied(), chapter, &profile.rules);
+        let log_likelihood = likelihood::log_likelihood(&groups, chapter, profile);
+        RunScore {
+            run: run.to_string(),
+            monsters: run_observations.len(),
+            log_likelihood,
+            per_monster: log_likelihood / run_observations.len() as f64
+        }
+    }).collect();
+
+    let groups = likelihood::group_observations(chapter_observations.iter().copied(), chapter, &profile.rules);
+    ChapterReport {
+        chapter: chapter.name().to_string(),
+        monsters: chapter_observations.len(),
+        log_likelihood: likelihood::log_likelihood(&groups, chapter, profile),
+        total: total.test(),
+        bins: bin_reports,
+        runs,
+        residuals
+    }
+}
+
+/// Observed and expected amount of slots and bounties in every cell
+#[derive(Clone, Debug, Default)]
+struct CellTotals {
+    observed:Vec<f64>,
+    expected:Vec<f64>,
+    /// amount of slots or monsters the cell is counted over
+    trials:Vec<f64>,
+    /// degrees of freedom of the multinomial blocks (the slots and every slotless gift type) these cells came from
+    degrees_of_freedom:usize
+}
+
+impl CellTotals {
+    fn of(groups:&[Group], chapter:Chapter, profile:&ModelProfile) -> CellTotals {
+        let slot_cells = likelihood::slot_cell_count(&profile.rules);
+        let slotless_cells = likelihood::slotless_cell_count(&profile.rules);
+        let mut observed = vec![0.0; slot_cells + slotless_cells];
+        let mut expected = vec![0.0; slot_cells + slotless_cells];
+        let mut trials = vec![0.0; slot_cells + slotless_cells];
+        for group in groups {
+            let model = chapter_sequence(group.karma, chapter, group.wonderful, profile);
+            let model_cells = likelihood::slot_cells(&model, &profile.rules).into_iter().chain(likelihood::slotless_cells(&model));
+            let group_cells = group.slots.iter().chain(&group.slotless);
+            for (i, (model_cell, group_cell)) in model_cells.zip(group_cells).enumerate() {
+                observed[i] += group_cell;
+                expected[i] += model_cell * group.monsters;
+                trials[i] += if i < slot_cells {group.monsters * profile.rules.slots as f64} else {group.monsters};
+            }
+        }
+        let mut cells = CellTotals { observed, expected, trials, degrees_of_freedom: 0 };
+        let used = |range:std::ops::Range<usize>| range.filter(|i| cells.possible(*i)).count();
+        let block = profile.rules.ranks + 1;
+        cells.degrees_of_freedom = used(0..slot_cells).saturating_sub(1) + (slot_cells..slot_cells + slotless_cells).step_by(block)
+            .map(|start| used(start..start + block).saturating_sub(1))
+            .sum::<usize>();
+        cells
+    }
+
+    /// The same floor as the likelihood: cells with a lower chance are impossible in the model.
+    /// They are left out of the tests, the chi-square approximation breaks down for them
+    fn possible(&self, i:usize) -> bool {
+        self.expected[i] > likelihood::MIN_CHANCE * self.trials[i]
+    }
+
+    fn add(&mut self, other:&CellTotals) {
+        if self.observed.is_empty() {
+            *self = other.clone();
+            return;
+        }
+        for i in 0..self.observed.len() {
+            self.observed[i] += other.observed[i];
+            self.expected[i] += other.expected[i];
+            self.trials[i] += other.trials[i];
+        }
+        self.degrees_of_freedom += other.degrees_of_freedom;
+    }
+
+    fn used_cells(&self) -> impl Iterator<Item = (usize, f64, f64)> + '_ {
+        (0..self.observed.len()).filter(|i| self.possible(*i)).map(|i| (i, self.observed[i], self.expected[i]))
+    }
+
+    /// Cells that were observed although the model says they are impossible
+    fn impossible_cells(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.observed.len()).filter(|i| !self.possible(*i) && self.observed[*i] > 0.0)
+    }
+
+    fn test(&self) -> FitTest {
+        let chi_square:f64 = self.used_cells().map(|(_, o, e)| (o - e) * (o - e) / e).sum();
+        let likelihood_ratio:f64 = 2.0 * self.used_cells()
+            .filter(|(_, o, _)| *o > 0.0)
+            .map(|(_, o, e)| o * (o / e).ln())
+            .sum::<f64>();
+        let df = self.degrees_of_freedom;
+        let impossible_cells = self.impossible_cells().count();
+        //a single impossible observation rejects the model
+        let p = |statistic:f64| if impossible_cells > 0 {0.0} else {chi_square_survival(statistic, df)};
+        FitTest {
+            chi_square,
+            likelihood_ratio,
+            degrees_of_freedom: df,
+            chi_square_p: p(chi_square),
+            likelihood_ratio_p: p(likelihood_ratio),
+            impossible_cells
+        }
+    }
+
+    fn residuals(&self, karma:f64, rules:&GameRules) -> Vec<Residual> {
+        let slot_cells = likelihood::slot_cell_count(rules);
+        let residual = |i:usize, impossible:bool| {
+            let (observed, expected) = (self.observed[i], self.expected[i]);
+            let floored = f64::max(expected, likelihood::MIN_CHANCE * self.trials[i]);
+            Residual {
+                cell: if i < slot_cells {likelihood::slot_cell_name(i, rules)} else {likelihood::slotless_cell_name(i - slot_cells, rules)},
+                karma,
+                observed,
+                expected,
+                residual: (observed - floored) / floored.sqrt(),
+                impossible
+            }
+        };
+        self.used_cells().map(|(i, _, _)| residual(i, false))
+            .chain(self.impossible_cells().map(|i| residual(i, true)))
+            .collect()
+    }
+}
+
+// ------------------- CHI-SQUARE DISTRIBUTION -----------------
+
+/// Chance that a chi-square distributed value with df degrees of freedom is at least x
+fn chi_square_survival(x:f64, df:usize) -> f64 {
+    if df == 0 {
+        return 1.0;
+    }
+    upper_gamma(df as f64 / 2.0, x / 2.0)
+}
+
+/// Regularized upper incomplete gamma function Q(a, x)
+fn upper_gamma(a:f64, x:f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        //series of the lower gamma function
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..500 {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        1.0 - sum * prefactor
+    } else {
+        //continued fraction of the upper gamma function (modified Lentz)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for n in 1..500 {
+            let an = -(n as f64) * (n as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {d = tiny}
+            c = b + an / c;
+            if c.abs() < tiny {c = tiny}
+            d = 1.0 / d;
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        fraction * prefactor
+    }
+}
+
+/// Lanczos approximation of ln(gamma(x))
+fn ln_gamma(x:f64) -> f64 {
+    const COEFFICIENTS:[f64;9] = [
+        0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8,
+        771.323_428_777_653_1, -176.615_029_162_140_6, 12.507_343_278_686_905,
+        -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7
+    ];
+    if x < 0.5 {
+        //reflection formula
+        return std::f64::consts::PI.ln() - (std::f64::consts::PI * x).sin().ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// ------------------- OUTPUT -----------------
+
+fn markdown(report:&Report) -> String {
+    let mut text = String::from("# Goodness of fit\n");
+    for chapter in &report.chapters {
+        let total = &chapter.total;
+        text += &format!("\n## {}\n\n", chapter.chapter);
+        text += &format!("{} monsters, log-likelihood {:.3}\n\n", chapter.monsters, chapter.log_likelihood);
+        text += &format!("All bins: chi-square {:.2} (p = {:.4}), G {:.2} (p = {:.4}), {} degrees of freedom\n\n",
+            total.chi_square, total.chi_square_p, total.likelihood_ratio, total.likelihood_ratio_p, total.degrees_of_freedom);
+        if total.impossible_cells > 0 {
+            text += &format!("**Observed but impossible in the model: {} cells**, they are left out of the chi-square and G statistics\n\n", total.impossible_cells);
+        }
+
+        text += "| karma | monsters | chi-square | p | G | p | df | impossible |\n|---|---|---|---|---|---|---|---|\n";
+        for bin in &chapter.bins {
+            let test = &bin.test;
+            text += &format!("| {:.3} | {} | {:.2} | {:.4} | {:.2} | {:.4} | {} | {} |\n",
+                bin.karma, bin.monsters, test.chi_square, test.chi_square_p, test.likelihood_ratio, test.likelihood_ratio_p, test.degrees_of_freedom, test.impossible_cells);
+        }
+
+        text += "\n### Runs\n\n| run | monsters | log-likelihood | per monster |\n|---|---|---|---|\n";
+        for run in &chapter.runs {
+            text += &format!("| {} | {} | {:.3} | {:.4} |\n", run.run, run.monsters, run.log_likelihood, run.per_monster);
+        }
+
+        text += "\n### Largest residuals\n\n| cell | karma | observed | expected | residual |\n|---|---|---|---|---|\n";
+        for residual in &chapter.residuals {
+            let value = if residual.impossible {"impossible".to_string()} else {format!("{:.2}", residual.residual)};
+            text += &format!("| {} | {:.3} | {} | {:.2} | {} |\n", residual.cell, residual.karma, residual.observed, residual.expected, value);
+        }
+    }
+    text
+}
+
+// ------------------- COMMAND LINE -----------------
+
+/// `karma_plot report`, compares a gift log to the model and prints the result as markdown or json
+pub fn command(args:&[String]) -> Result<(), String> {
+    let mut log_path = None;
+    let mut profile = ModelProfile::default();
+    let mut bin_width = 0.1;
+    let mut residual_count = 10;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value\n{USAGE}"));
+        match arg.as_str() {
+            "--profile" => profile = ModelProfile::load(value()?)?,
+            "--bin-width" => {
+                let width = value()?;
+                bin_width = width.parse().ok().filter(|w:&f64| *w > 0.0).ok_or_else(|| format!("invalid bin width '{width}'"))?;
+            },
+            "--residuals" => {
+                let count = value()?;
+                residual_count = count.parse().map_err(|_| format!("invalid residual count '{count}'"))?;
+            },
+            "--format" => json = match value()?.as_str() {
+                "markdown" => false,
+                "json" => true,
+                format => return Err(format!("unknown format '{format}'\n{USAGE}"))
+            },
+            _ if log_path.is_none() && !arg.starts_with("--") => log_path = Some(arg.clone()),
+            _ => return Err(format!("Unknown argument {arg}\n{USAGE}"))
+        }
+    }
+
+    let log_path = log_path.ok_or_else(|| format!("Missing gift log\n{USAGE}"))?;
+    let observations = observations::load_observations(&log_path)?;
+    let report = report(&observations, &profile, bin_width, residual_count);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("Report can always be serialized"));
+    } else {
+        print!("{}", markdown(&report));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chi_square_survival_matches_the_tables() {
+        assert!((chi_square_survival(3.841, 1) - 0.05).abs() < 1e-4);
+        assert!((chi_square_survival(18.307, 10) - 0.05).abs() < 1e-4);
+        assert!((chi_square_survival(1.0, 1) - 0.3173).abs() < 1e-4);
+        assert_eq!(chi_square_survival(5.0, 0), 1.0);
+    }
+
+    #[test]
+    fn upper_gamma_of_one_is_the_exponential() {
+        //x = 0.5 uses the series and x = 3 the continued fraction
+        for x in [0.5, 2.0, 3.0, 10.0] {
+            assert!((upper_gamma(1.0, x) - (-x).exp()).abs() < 1e-12, "Q(1, {x})");
+        }
+        assert_eq!(upper_gamma(2.5, 0.0), 1.0);
+    }
+
+    #[test]
+    fn ln_gamma_matches_known_values() {
+        assert!(ln_gamma(1.0).abs() < 1e-12);
+        assert!((ln_gamma(5.0) - 24.0f64.ln()).abs() < 1e-12);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-12);
+        //below 0.5 the reflection formula is used
+        assert!((ln_gamma(0.25) - 3.625_609_908_221_908f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn impossible_cells_are_no_degrees_of_freedom() {
+        //at karma 0 story monsters have no 3 star gifts and no bounties
+        let profile = ModelProfile::default();
+        let rules = &profile.rules;
+        let model = chapter_sequence(0.0, Chapter::Story, 0, &profile);
+        let monsters = 10.0;
+        let mut group = Group {
+            karma: 0.0,
+            wonderful: 0,
+            monsters,
+            slots: likelihood::slot_cells(&model, rules).iter().map(|cell| cell * monsters).collect(),
+            slotless: likelihood::slotless_cells(&model).iter().map(|cell| cell * monsters).collect()
+        };
+        let cells = CellTotals::of(std::slice::from_ref(&group), Chapter::Story, &profile);
+        //7 possible slot cells, and only the empty cell of the bounty
+        assert_eq!(cells.degrees_of_freedom, 6);
+        let test = cells.test();
+        assert_eq!(test.impossible_cells, 0);
+        assert!(test.chi_square < 1e-9);
+
+        //a single observed 3 star power gift rejects the model
+        group.slots[2] += 1.0;
+        let test = CellTotals::of(&[group], Chapter::Story, &profile).test();
+        assert_eq!(test.impossible_cells, 1);
+        assert_eq!(test.degrees_of_freedom, 6);
+        assert_eq!(test.chi_square_p, 0.0);
+    }
+}
@@ -0,0 +1,290 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::heatmap::{HeatmapSettings, MAX_WONDERFUL_COUNT};
+use crate::utility::UtilityWeights;
+use crate::{orders_text, Chapter, ChapterMonsters, ChapterView, DomainSettings, GType, GiftView, ModelProfile, PlotProgram};
+
+// ------------------- SCENARIOS -----------------
+
+/// Folder in the working directory where named scenarios are stored
+const SCENARIO_FOLDER:&str = "scenarios";
+
+/// Everything needed to show the same chart again
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub name:String,
+    pub chapter:Chapter,
+    pub domain_settings:DomainSettings,
+    pub wonderful_count:usize,
+    pub view:GiftView,
+    pub monsters:ChapterMonsters,
+    pub chapter_view:ChapterView,
+    pub show_empty:bool,
+    pub show_spread:bool,
+    pub spread_rank:usize,
+    pub heatmap:ScenarioHeatmap,
+    pub show_utility:bool,
+    pub utility_weights:UtilityWeights,
+    pub show_cursor:bool,
+    pub show_donut:bool,
+    pub cursor_karma:f64,
+    /// None for the default orders of the chapter
+    pub orders:Option<Vec<Vec<GType>>>,
+    pub profile:ModelProfile
+}
+
+/// The heatmap settings of a scenario, the values are calculated when the scenario is applied
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioHeatmap {
+    pub show:bool,
+    pub gift_type:GType,
+    pub rank:usize
+}
+
+impl Default for ScenarioHeatmap {
+    fn default() -> Self {
+        ScenarioHeatmap::of(&HeatmapSettings::default())
+    }
+}
+
+impl ScenarioHeatmap {
+    fn of(settings:&HeatmapSettings) -> ScenarioHeatmap {
+        ScenarioHeatmap { show: settings.show, gift_type: settings.gift_type, rank: settings.rank }
+    }
+}
+
+impl Scenario {
+    pub fn of(program:&PlotProgram, name:&str) -> Scenario {
+        Scenario {
+            name: name.to_string(),
+            chapter: program.chapter,
+            domain_settings: program.domain_settings.clone(),
+            wonderful_count: program.wonderful_count,
+            view: program.view,
+            monsters: program.monsters,
+            chapter_view: program.chapter_view,
+            show_empty: program.show_empty,
+            show_spread: program.show_spread,
+            spread_rank: program.spread_rank,
+            heatmap: ScenarioHeatmap::of(&program.heatmap),
+            show_utility: program.utility.show,
+            utility_weights: program.utility.weights.clone(),
+            show_cursor: program.cursor.show,
+            show_donut: program.show_donut,
+            cursor_karma: program.cursor.karma,
+            orders: program.custom_orders.clone(),
+            profile: program.profile.clone()
+        }
+    }
+
+    /// Scenarios are edited by hand and shared as codes, so values the ui does not allow are rejected
+    pub fn validate(&self) -> Result<(), String> {
+        self.domain_settings.validate()?;
+        self.profile.validate()?;
+        if self.wonderful_count > MAX_WONDERFUL_COUNT {
+            return Err(format!("{} wonderful gifts, should be 0 to {MAX_WONDERFUL_COUNT}", self.wonderful_count));
+        }
+        for chapter in Chapter::ALL {
+            self.monsters.of(chapter).validate().map_err(|e| format!("{}: {e}", chapter.name()))?;
+        }
+        let settings = &self.domain_settings;
+        if (self.show_cursor || self.show_donut) && !(settings.min..=settings.max).contains(&self.cursor_karma) {
+            return Err(format!("cursor at karma {}, should be {} to {}", self.cursor_karma, settings.min, settings.max));
+        }
+        Ok(())
+    }
+
+    /// Replaces the settings of the program with the scenario, the program still has to be recalculated.
+    /// Nothing changes if the scenario is not valid
+    pub fn apply(&self, program:&mut PlotProgram) -> Result<(), String> {
+        self.validate()?;
+        program.chapter = self.chapter;
+        program.domain_settings = self.domain_settings.clone();
+        program.wonderful_count = self.wonderful_count;
+        program.view = self.view;
+        program.monsters = self.monsters;
+        program.chapter_view = self.chapter_view;
+        program.show_empty = self.show_empty;
+        program.show_spread = self.show_spread;
+        program.profile = self.profile.clone();
+        program.profile.set_rules(self.profile.rules);
+        program.spread_rank = self.spread_rank.min(program.profile.rules.ranks - 1);
+        program.heatmap.show = self.heatmap.show;
+        program.heatmap.gift_type = self.heatmap.gift_type;
+        program.heatmap.rank = self.heatmap.rank.min(program.profile.rules.ranks - 1);
+        program.utility.show = self.show_utility;
+        program.utility.weights = self.utility_weights.clone();
+        //the donut is moved with the cursor
+        program.cursor.show = self.show_cursor || self.show_donut;
+        program.show_donut = self.show_donut;
+        program.cursor.karma = self.cursor_karma;
+        program.orders_text = self.orders.as_deref().map(orders_text).unwrap_or_default();
+        Ok(())
+    }
+
+    pub fn load(path:&Path) -> Result<Scenario, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        let scenario:Scenario = serde_json::from_str(&text).map_err(|e| format!("Invalid scenario {}: {e}", path.display()))?;
+        scenario.validate().map_err(|e| format!("Invalid scenario {}: {e}", path.display()))?;
+        Ok(scenario)
+    }
+
+    /// Saves the scenario in the scenario folder under its name
+    pub fn save(&self) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(SCENARIO_FOLDER).map_err(|e| format!("Could not create {SCENARIO_FOLDER}: {e}"))?;
+        let path = scenario_path(&self.name);
+        let text = serde_json::to_string_pretty(self).expect("Scenario can always be serialized");
+        std::fs::write(&path, text).map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// File of a named scenario, characters that can not be used in file names are replaced
+fn scenario_path(name:&str) -> PathBuf {
+    let file_name:String = name.chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' || c == '.' {c} else {'_'})
+        .collect();
+    Path::new(SCENARIO_FOLDER).join(format!("{}.json", file_name.trim()))
+}
+
+/// Finds a scenario given on the command line, either as a file or as the name of a saved scenario
+pub fn find(name_or_path:&str) -> Result<Scenario, String> {
+    let path = Path::new(name_or_path);
+    if path.is_file() {
+        Scenario::load(path)
+    } else {
+        Scenario::load(&scenario_path(name_or_path))
+    }
+}
+
+/// All saved scenarios, sorted by name. Files that can not be read are skipped
+pub fn saved_scenarios() -> Vec<Scenario> {
+    let Ok(entries) = std::fs::read_dir(SCENARIO_FOLDER) else {
+        return Vec::new();
+    };
+    let mut scenarios:Vec<Scenario> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| Scenario::load(&path).ok())
+        .collect();
+    scenarios.sort_by(|x, y| x.name.cmp(&y.name));
+    scenarios
+}
+
+// ------------------- SCENARIO CODES -----------------
+// A scenario code is the scenario as json in base64, so it can be pasted in a chat message.
+// The name is left out, and the profile as well if it is the default profile, to keep the code short.
+// Codes look like kp1.<base64>, where 1 is the version of the code
+
+/// Version of the scenario code, codes of other versions are rejected
+const CODE_VERSION:u64 = 1;
+/// Start of every scenario code
+const CODE_PREFIX:&str = "kp";
+
+impl Scenario {
+    pub fn code(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("Scenario can always be serialized");
+        let fields = value.as_object_mut().expect("Scenario is a json object");
+        fields.remove("name");
+        if self.profile == ModelProfile::default() {
+            fields.remove("profile");
+        }
+        let json = serde_json::to_string(&value).expect("Scenario can always be serialized");
+        format!("{CODE_PREFIX}{CODE_VERSION}.{}", URL_SAFE_NO_PAD.encode(json))
+    }
+
+    pub fn from_code(code:&str) -> Result<Scenario, String> {
+        let code = code.trim();
+        let (version, data) = code.strip_prefix(CODE_PREFIX)
+            .and_then(|code| code.split_once('.'))
+            .ok_or_else(|| "This is not a scenario code".to_string())?;
+        if version.parse() != Ok(CODE_VERSION) {
+            return Err(format!("Scenario code version {version} is not supported, only version {CODE_VERSION}"));
+        }
+        let json = URL_SAFE_NO_PAD.decode(data).map_err(|e| format!("Invalid scenario code: {e}"))?;
+        let scenario:Scenario = serde_json::from_slice(&json).map_err(|e| format!("Invalid scenario code: {e}"))?;
+        scenario.validate().map_err(|e| format!("Invalid scenario code: {e}"))?;
+        Ok(scenario)
+    }
+}
+
+// ------------------- SCENARIO PANEL -----------------
+
+#[derive(Clone, Default)]
+pub struct ScenarioPanel {
+    pub open:bool,
+    pub name:String,
+    /// result of the last save or load
+    pub status:String,
+    /// scenario code pasted by the user
+    pub code:String,
+    pub saved:Vec<Scenario>
+}
+
+impl ScenarioPanel {
+    pub fn refresh(&mut self) {
+        self.saved = saved_scenarios();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Linear;
+
+    fn scenario() -> Scenario {
+        Scenario {
+            name: "late alter story".to_string(),
+            chapter: Chapter::AStory,
+            wonderful_count: 2,
+            show_spread: true,
+            show_cursor: true,
+            cursor_karma: 0.5,
+            ..Scenario::default()
+        }
+    }
+
+    #[test]
+    fn code_round_trip_with_the_default_profile() {
+        let code = scenario().code();
+        assert!(code.starts_with("kp1."));
+        let decoded = Scenario::from_code(&code).unwrap();
+        assert_eq!(decoded.code(), code);
+        //the name and the default profile are left out of the code
+        assert_eq!(decoded.name, "");
+        assert!(decoded.profile == ModelProfile::default());
+        assert_eq!((decoded.chapter, decoded.wonderful_count, decoded.cursor_karma), (Chapter::AStory, 2, 0.5));
+    }
+
+    #[test]
+    fn code_round_trip_with_a_custom_profile() {
+        let mut scenario = scenario();
+        scenario.profile.burden_rank_down = 0.4;
+        scenario.profile.formulas_mut(GType::Quick, Chapter::AStory).chosen[0] = Linear::new(0.1, 0.3, 0.0, 0.8);
+        let code = scenario.code();
+        assert!(code.len() > Scenario::default().code().len());
+        let decoded = Scenario::from_code(&code).unwrap();
+        assert!(decoded.profile == scenario.profile);
+        assert_eq!(decoded.code(), code);
+    }
+
+    #[test]
+    fn codes_of_other_versions_are_rejected() {
+        let code = scenario().code().replacen("kp1.", "kp2.", 1);
+        let error = |code:&str| Scenario::from_code(code).err();
+        assert_eq!(error(&code).as_deref(), Some("Scenario code version 2 is not supported, only version 1"));
+        assert_eq!(error("late alter story").as_deref(), Some("This is not a scenario code"));
+    }
+
+    #[test]
+    fn invalid_base64_is_rejected() {
+        let error = Scenario::from_code("kp1.not*base64").err().unwrap_or_default();
+        assert!(error.starts_with("Invalid scenario code: "), "{error}");
+    }
+}
@@ -0,0 +1,123 @@
+use crate::number::{self, Number};
+use crate::{pow, AverageRank, WONDERFUL_RANK};
+
+// ------------------- SLOT STATES -----------------
+// Every try adds its gift to the first empty slot, so the slots of a monster are always filled from the front
+// and the amount of filled slots says which slots are empty. The 3 star power chance also depends on the
+// amount of wonderful power gifts the monster already received, so that amount is part of the state as well.
+//
+// A try with chance p changes a state with fewer filled slots than there are slots:
+//   (filled, wonderful) -> (filled, wonderful)          chance 1 - p
+//   (filled, wonderful) -> (filled + 1, wonderful)      chance p * (1 - w)
+//   (filled, wonderful) -> (filled + 1, wonderful + 1)  chance p * w
+// where w is the chance that the added gift is a wonderful power gift. A monster with every slot filled stays as it is.
+// The gift of the second and third transition ends up in slot `filled`, so the average amount of gifts
+// every slot receives is the chance of the states that move, and the chances of all states always add up to 1.
+
+/// Chance of every amount of filled slots from the chance that each slot is empty, index i is the chance of exactly i filled slots.
+/// Slots are filled from the front, so a slot can only be empty if all slots after it are empty too
+pub fn filled_chances<N:Number>(remaining:&[N]) -> Vec<N> {
+    let slot_count = remaining.len();
+    (0..=slot_count).map(|filled| match filled {
+        0 => remaining[0].clone(),
+        _ if filled == slot_count => N::one() - remaining[slot_count - 1].clone(),
+        _ => remaining[filled].clone() - remaining[filled - 1].clone()
+    }).collect()
+}
+
+/// Chance of every state of a monster, indexed by the amount of filled slots and then by the amount of wonderful power gifts
+#[derive(Clone, Debug)]
+pub struct SlotState<N:Number> {
+    chances:Vec<Vec<N>>
+}
+
+impl<N:Number> SlotState<N> {
+    /// States after the gifts that are placed before the order starts (the tower blessings and burdens).
+    /// Only the chance of every amount of filled slots is known, so no state starts with wonderful power gifts of the order
+    pub fn from_remaining(remaining:&[N]) -> SlotState<N> {
+        let slot_count = remaining.len();
+        let mut chances = vec![vec![N::zero(); slot_count + 1]; slot_count + 1];
+        for (row, filled_chance) in chances.iter_mut().zip(filled_chances(remaining)) {
+            row[0] = filled_chance;
+        }
+        SlotState { chances }
+    }
+
+    /// Chance that each slot is still empty, which is the chance that fewer slots than its index + 1 are filled
+    pub fn remaining(&self) -> Vec<N> {
+        let mut empty = N::zero();
+        self.chances[..self.chances.len() - 1].iter().map(|row| {
+            empty += number::sum(row.iter().cloned());
+            empty.clone()
+        }).collect()
+    }
+
+    /// Tries to add a gift with the given chance to the first empty slot, and returns the amount of gifts with at least every rank
+    /// that ends up in every slot. Every rank is reached by ranking up the gift from the rank below.
+    ///
+    /// `wonderful_count` is only given for power gifts, it is the amount of wonderful gifts the monster had before the order.
+    /// The chance of a 3 star power gift is halved for those and for every wonderful gift of the order
+    pub fn try_gift(&mut self, chance:N, rank_up:&[N], wonderful_count:Option<usize>) -> Vec<AverageRank<N>> {
+        let slot_count = self.chances.len() - 1;
+        let mut added = vec![vec![N::zero(); rank_up.len() + 1]; slot_count];
+        let mut next = vec![vec![N::zero(); slot_count + 1]; slot_count + 1];
+        for (filled, row) in self.chances.iter().enumerate() {
+            //there can not be more wonderful gifts than filled slots
+            for (wonderful, state) in row.iter().enumerate().take(filled + 1) {
+                if filled == slot_count {
+                    next[filled][wonderful] += state.clone();
+                    continue;
+                }
+                let moved = state.clone() * chance.clone();
+                let mut rank_added = moved.clone();
+                let mut wonderful_added = N::zero();
+                for (rank, total) in added[filled].iter_mut().enumerate() {
+                    if rank > 0 {
+                        rank_added = rank_added * rank_up[rank - 1].clone();
+                    }
+                    if let (WONDERFUL_RANK, Some(count)) = (rank, wonderful_count) {
+                        rank_added = rank_added * pow(N::from_f64(0.5), count + wonderful);
+                        wonderful_added = rank_added.clone();
+                    }
+                    *total += rank_added.clone();
+                }
+                next[filled][wonderful] += state.clone() - moved.clone();
+                next[filled + 1][wonderful] += moved - wonderful_added.clone();
+                next[filled + 1][wonderful + 1] += wonderful_added;
+            }
+        }
+        self.chances = next;
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number::Exact;
+
+    fn total(state:&SlotState<Exact>) -> Exact {
+        number::sum(state.chances.iter().flatten().cloned())
+    }
+
+    #[test]
+    fn state_chances_add_up_to_one() {
+        let mut state = SlotState::from_remaining(&[Exact::from_f64(0.0), Exact::from_f64(0.4), Exact::from_f64(1.0)]);
+        assert_eq!(total(&state), Exact::one());
+        let rank_up = [Exact::from_f64(0.7), Exact::from_f64(0.35)];
+        for (chance, wonderful_count) in [(0.9, Some(1)), (0.3, None), (0.65, Some(0)), (1.0, Some(2))] {
+            state.try_gift(Exact::from_f64(chance), &rank_up, wonderful_count);
+            assert_eq!(total(&state), Exact::one());
+        }
+    }
+
+    #[test]
+    fn added_gifts_are_the_filled_slots() {
+        let mut state = SlotState::from_remaining(&[Exact::one(), Exact::one(), Exact::one()]);
+        let before = state.remaining();
+        let added = state.try_gift(Exact::from_f64(0.6), &[Exact::from_f64(0.5), Exact::from_f64(0.2)], Some(1));
+        for ((slot, before), after) in added.iter().zip(before).zip(state.remaining()) {
+            assert_eq!(slot[0].clone(), before - after);
+        }
+    }
+}
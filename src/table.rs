@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use crate::PlotProgram;
+
+// ------------------- DATA TABLE -----------------
+
+#[derive(Clone, Default)]
+pub struct Column {
+    pub name:String,
+    pub values:Vec<f64>
+}
+
+#[derive(Clone, Default)]
+pub struct DataTable {
+    pub open:bool,
+    /// only gift columns with this text in their name are shown
+    pub column_filter:String,
+    /// only karma values from..=to are shown, in the unit of the x axis
+    pub karma_filter:bool,
+    pub from:f64,
+    pub to:f64,
+    /// index of the sorted column, and whether it is sorted from high to low
+    pub sort:Option<(usize, bool)>,
+    /// columns and sorted rows of the last rebuild, the table is only rebuilt when the data or the filters change
+    pub data:Vec<Column>,
+    pub rows:Vec<usize>
+}
+
+impl DataTable {
+    /// The karma column followed by every rank of the visible gifts that pass the filter
+    pub fn columns(&self, program:&PlotProgram) -> Vec<Column> {
+        let mut gift_columns = Vec::new();
+        for gift_type in program.shown_gift_types() {
+            let ranks = program.gift_chance.of(gift_type);
+            let rules = &program.profile.rules;
+            for rank in 0..rules.ranks {
+                let column_name = format!("{} {}", gift_type.name(), rules.rank_name(rank));
+                gift_columns.push(Column { name: column_name.trim().to_string(), values: ranks.iter().map(|ranks| ranks[rank]).collect() });
+            }
+        }
+        if program.empty_slots_shown() {
+            gift_columns.push(Column { name: "empty slots".to_string(), values: program.gift_chance.empty.clone() });
+        }
+
+        let karma = program.karma_range.iter().map(|karma| program.x_value(*karma)).collect();
+        let mut columns = vec![Column { name: program.domain_settings.unit.axis_label().to_string(), values: karma }];
+        let filter = self.column_filter.trim().to_lowercase();
+        columns.extend(gift_columns.into_iter().filter(|column| column.name.to_lowercase().contains(&filter)));
+        columns
+    }
+
+    /// Indices of the karma values that pass the filter, in the sorted order
+    pub fn rows(&self, columns:&[Column]) -> Vec<usize> {
+        let karma = &columns[0].values;
+        let mut rows:Vec<usize> = (0..karma.len())
+            .filter(|i| !self.karma_filter || (self.from..=self.to).contains(&karma[*i]))
+            .collect();
+        if let Some((column, descending)) = self.sort {
+            if let Some(column) = columns.get(column) {
+                rows.sort_by(|x, y| column.values[*x].total_cmp(&column.values[*y]));
+                if descending {
+                    rows.reverse();
+                }
+            }
+        }
+        rows
+    }
+
+    /// Sorts on the clicked column, clicking it again switches between low to high and high to low
+    pub fn click_header(&mut self, column:usize) {
+        self.sort = match self.sort {
+            Some((sorted, descending)) if sorted == column => Some((column, !descending)),
+            _ => Some((column, false))
+        };
+    }
+}
+
+/// Tab separated table with a header, for pasting into spreadsheets
+pub fn tsv(columns:&[Column], rows:&[usize]) -> String {
+    let mut text = columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>().join("\t");
+    text.push('\n');
+    for row in rows {
+        let values:Vec<String> = columns.iter().map(|column| column.values[*row].to_string()).collect();
+        writeln!(text, "{}", values.join("\t")).unwrap();
+    }
+    text
+}
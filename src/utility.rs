@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::gifts::GiftMap;
+use crate::{exact_ranks, GType, GiftChance};
+
+// ------------------- UTILITY WEIGHTS -----------------
+
+/// File in the working directory with the saved weights of every build
+const BUILDS_FILE:&str = "builds.json";
+
+/// Part of the total gain at which the utility counts as flattened
+pub const FLAT_FRACTION:f64 = 0.95;
+
+/// Value of a single gift of every type and exact rank, indexed by gift type and then by rank.
+/// Ranks without a value are worth nothing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UtilityWeights {
+    pub values:GiftMap<Vec<f64>>
+}
+
+impl Default for UtilityWeights {
+    /// Every star is worth one point, and burdens cost as much as they would be worth as a gift
+    fn default() -> Self {
+        let mut values = GiftMap::from_fn(|_| vec![1.0, 2.0, 3.0]);
+        values[GType::Burden] = vec![-1.0, -2.0, -3.0];
+        UtilityWeights { values }
+    }
+}
+
+impl UtilityWeights {
+    /// Expected utility of one karma value of the gift chances
+    fn utility(&self, chance:&GiftChance, index:usize) -> f64 {
+        GType::ALL.iter().map(|gift_type| {
+            let exact = exact_ranks(&chance.of(*gift_type)[index]);
+            exact.iter().zip(&self.values[*gift_type]).map(|(amount, value)| amount * value).sum::<f64>()
+        }).sum()
+    }
+
+    /// Adds a value for every rank the rules allow, a new rank is worth one more than the rank below it
+    pub fn extend_to(&mut self, ranks:usize) {
+        for values in self.values.values_mut() {
+            while values.len() < ranks {
+                let value = values.last().map_or(1.0, |last| last + last.signum());
+                values.push(value);
+            }
+        }
+    }
+
+    /// Expected utility for every karma value of the gift chances
+    pub fn curve(&self, chance:&GiftChance) -> Vec<f64> {
+        (0..chance.empty.len()).map(|i| self.utility(chance, i)).collect()
+    }
+}
+
+/// Indices in the utility curve of the highest utility,
+/// and of the first karma value that gets FLAT_FRACTION of the gain between the lowest karma and the highest utility
+#[derive(Copy, Clone, Debug)]
+pub struct Optimum {
+    pub best:usize,
+    pub flat:usize
+}
+
+pub fn optimum(curve:&[f64]) -> Option<Optimum> {
+    let first = *curve.first()?;
+    let mut best = 0;
+    for (i, utility) in curve.iter().enumerate() {
+        if *utility > curve[best] {
+            best = i;
+        }
+    }
+    let target = first + FLAT_FRACTION * (curve[best] - first);
+    let flat = curve.iter().position(|utility| *utility >= target).unwrap_or(best).min(best);
+    Some(Optimum { best, flat })
+}
+
+// ------------------- SAVED BUILDS -----------------
+
+pub fn load_builds() -> Result<BTreeMap<String, UtilityWeights>, String> {
+    let text = match std::fs::read_to_string(BUILDS_FILE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(format!("Could not read {BUILDS_FILE}: {e}"))
+    };
+    serde_json::from_str(&text).map_err(|e| format!("Invalid builds in {BUILDS_FILE}: {e}"))
+}
+
+pub fn save_builds(builds:&BTreeMap<String, UtilityWeights>) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(builds).expect("Builds can always be serialized");
+    std::fs::write(BUILDS_FILE, text).map_err(|e| format!("Could not write {BUILDS_FILE}: {e}"))
+}
+
+// ------------------- UTILITY PANEL -----------------
+
+#[derive(Clone, Default)]
+pub struct UtilitySettings {
+    pub show:bool,
+    pub weights:UtilityWeights,
+    /// name of the build the weights are saved as
+    pub build:String,
+    pub builds:BTreeMap<String, UtilityWeights>,
+    /// result of the last load or save
+    pub status:String
+}
+
+impl UtilitySettings {
+    pub fn refresh(&mut self) {
+        match load_builds() {
+            Ok(builds) => self.builds = builds,
+            Err(error) => self.status = error
+        }
+    }
+
+    /// Saves the current weights under the build name, other builds in the file are kept
+    pub fn save(&mut self) {
+        let name = self.build.trim().to_string();
+        if name.is_empty() {
+            self.status = "a build needs a name".to_string();
+            return;
+        }
+        self.refresh();
+        self.builds.insert(name.clone(), self.weights.clone());
+        self.status = match save_builds(&self.builds) {
+            Ok(()) => format!("{name} saved"),
+            Err(error) => error
+        };
+    }
+
+    pub fn delete(&mut self) {
+        self.refresh();
+        if self.builds.remove(self.build.trim()).is_none() {
+            self.status = format!("there is no build named {}", self.build.trim());
+            return;
+        }
+        self.status = match save_builds(&self.builds) {
+            Ok(()) => format!("{} deleted", self.build.trim()),
+            Err(error) => error
+        };
+    }
+}
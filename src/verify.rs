@@ -0,0 +1,180 @@
+use crate::number::{self, Exact, Number};
+use crate::scenario::{self, Scenario};
+use crate::{ordered_sequence, ordered_slots, slot_totals, Chapter, GType, ModelProfile, PlotProgram, USAGE};
+
+// ------------------- EXACT CHECK -----------------
+// The sequence engine is evaluated with exact rationals next to f64. The identities of the model have to hold exactly,
+// in f64 they only hold up to rounding errors, and the exact results show how far the f64 results drift
+
+/// Identities that every result of the model satisfies, in the order of identity_errors
+const IDENTITIES:[&str;4] = [
+    "every slot is filled or empty",
+    "the slot gifts and the empty slots add up to the amount of slots",
+    "the gifts of all slots add up to the totals",
+    "a higher rank is never more likely than the rank below"
+];
+
+/// Result of one identity over all karma values
+#[derive(Clone, Debug)]
+pub struct IdentityCheck {
+    pub identity:&'static str,
+    /// the error of the exact results is 0 for every karma value
+    pub holds:bool,
+    /// largest error of the exact results
+    pub exact_error:f64,
+    /// largest error of the f64 results
+    pub float_error:f64
+}
+
+/// Largest difference between an f64 result and its exact value
+#[derive(Clone, Debug, Default)]
+pub struct Drift {
+    pub difference:f64,
+    /// gift and rank, or the empty slots
+    pub value:String,
+    pub karma:f64
+}
+
+#[derive(Clone, Debug)]
+pub struct Verification {
+    pub identities:Vec<IdentityCheck>,
+    pub drift:Drift
+}
+
+impl Verification {
+    pub fn holds(&self) -> bool {
+        self.identities.iter().all(|check| check.holds)
+    }
+}
+
+fn abs<N:Number>(value:N) -> N {
+    if value < N::zero() {N::zero() - value} else {value}
+}
+
+/// Largest value, and 0 if all values are negative
+fn max<N:Number>(values:impl IntoIterator<Item = N>) -> N {
+    values.into_iter().fold(N::zero(), |max, value| if value > max {value} else {max})
+}
+
+/// Error of every identity at a single karma value, 0 when the identity holds
+fn identity_errors<N:Number>(karma:N, chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>]) -> [N;4] {
+    let rules = &profile.rules;
+    let (totals, empty_slots) = ordered_sequence(karma.clone(), chapter, wonderful_count, profile, orders);
+    let (remaining, slots) = ordered_slots(karma, chapter, wonderful_count, profile, orders);
+
+    let slot_filled = max(slots.iter().zip(&remaining).map(|(slot, empty)| {
+        abs(number::sum(GType::slot_types().map(|gift_type| slot[gift_type][0].clone())) + empty.clone() - N::one())
+    }));
+    let all_slots = abs(number::sum(GType::slot_types().map(|gift_type| totals[gift_type][0].clone())) + empty_slots - N::from_f64(rules.slots as f64));
+    let summed = slot_totals(&slots, rules);
+    let slot_sums = max(GType::slot_types().flat_map(|gift_type| {
+        summed[gift_type].iter().zip(&totals[gift_type]).map(|(sum, total)| abs(sum.clone() - total.clone())).collect::<Vec<_>>()
+    }));
+    let rank_order = max(slots.iter().chain([&totals]).flat_map(|gifts| gifts.values()).flat_map(|ranks| {
+        ranks.windows(2).map(|pair| pair[1].clone() - pair[0].clone()).collect::<Vec<_>>()
+    }));
+    [slot_filled, all_slots, slot_sums, rank_order]
+}
+
+/// Checks the identities of the model with exact and f64 numbers for every karma value,
+/// and finds the chart value where f64 drifts the most from the exact value
+pub fn verify(karma_range:&[f64], chapter:Chapter, wonderful_count:usize, profile:&ModelProfile, orders:&[Vec<GType>]) -> Verification {
+    let mut identities:Vec<IdentityCheck> = IDENTITIES.iter().map(|identity| IdentityCheck { identity, holds: true, exact_error: 0.0, float_error: 0.0 }).collect();
+    let mut drift = Drift::default();
+    for karma in karma_range {
+        let exact_karma = Exact::from_f64(*karma);
+        let exact_errors = identity_errors(exact_karma.clone(), chapter, wonderful_count, profile, orders);
+        let float_errors = identity_errors(*karma, chapter, wonderful_count, profile, orders);
+        for ((check, exact), float) in identities.iter_mut().zip(exact_errors).zip(float_errors) {
+            check.holds &= exact == Exact::zero();
+            check.exact_error = check.exact_error.max(exact.to_f64());
+            check.float_error = check.float_error.max(float);
+        }
+
+        let (exact, exact_empty) = ordered_sequence(exact_karma, chapter, wonderful_count, profile, orders);
+        let (float, float_empty) = ordered_sequence(*karma, chapter, wonderful_count, profile, orders);
+        let mut values = vec![("empty slots".to_string(), float_empty, exact_empty)];
+        for gift_type in GType::ALL {
+            for (rank, (float, exact)) in float[gift_type].iter().zip(&exact[gift_type]).enumerate() {
+                let name = format!("{} {}", gift_type.name(), profile.rules.rank_name(rank));
+                values.push((name.trim_end().to_string(), *float, exact.clone()));
+            }
+        }
+        for (value, float, exact) in values {
+            let difference = (float - exact.to_f64()).abs();
+            if difference > drift.difference {
+                drift = Drift { difference, value, karma: *karma };
+            }
+        }
+    }
+    Verification { identities, drift }
+}
+
+fn markdown(verification:&Verification, chapter:Chapter, karma_range:&[f64], wonderful_count:usize) -> String {
+    let mut text = String::from("# Exact check\n\n");
+    text += &format!("{}, {} karma values from {} to {}, {} wonderful gifts\n\n", chapter.name(), karma_range.len(),
+        karma_range.first().unwrap_or(&0.0), karma_range.last().unwrap_or(&0.0), wonderful_count);
+    text += "| identity | exact | largest f64 error |\n|---|---|---|\n";
+    for check in &verification.identities {
+        let exact = if check.holds {"holds".to_string()} else {format!("off by {:.3e}", check.exact_error)};
+        text += &format!("| {} | {} | {:.3e} |\n", check.identity, exact, check.float_error);
+    }
+    let drift = &verification.drift;
+    if drift.difference > 0.0 {
+        text += &format!("\nLargest drift of f64: {:.3e} for {} at karma {}\n", drift.difference, drift.value, drift.karma);
+    } else {
+        text += "\nThe f64 results are the closest f64 to the exact results\n";
+    }
+    text
+}
+
+// ------------------- COMMAND LINE -----------------
+
+/// `karma_plot verify`, runs the exact check for the chapter, karma values and orders of a scenario
+pub fn command(args:&[String]) -> Result<(), String> {
+    let mut scenario = Scenario::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value\n{USAGE}"));
+        match arg.as_str() {
+            "--scenario" => scenario = scenario::find(value()?)?,
+            "--code" => scenario = Scenario::from_code(value()?)?,
+            _ => return Err(format!("Unknown argument {arg}\n{USAGE}"))
+        }
+    }
+
+    let mut program = PlotProgram::default();
+    scenario.apply(&mut program)?;
+    program.recalc();
+    if !program.orders_status.is_empty() {
+        eprintln!("{}", program.orders_status);
+    }
+
+    let verification = verify(&program.karma_range, program.chapter, program.wonderful_count, &program.profile, &program.orders());
+    print!("{}", markdown(&verification, program.chapter, &program.karma_range, program.wonderful_count));
+    if !verification.holds() {
+        return Err("An identity of the model does not hold exactly".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default_orders;
+
+    #[test]
+    fn identities_hold_exactly_in_every_chapter() {
+        //coarser than the default domain to keep the exact evaluation fast, it still crosses the kinks of the clamps
+        let karma_range:Vec<f64> = (0..=30).map(|i| i as f64 * 0.1).collect();
+        let profile = ModelProfile::default();
+        for chapter in Chapter::ALL {
+            for wonderful_count in [0, 2] {
+                let verification = verify(&karma_range, chapter, wonderful_count, &profile, &default_orders(chapter));
+                assert!(verification.holds(), "{} with {wonderful_count} wonderful gifts: {:?}", chapter.name(), verification.identities);
+                assert!(verification.drift.difference < 1e-12, "{} drifts {:?}", chapter.name(), verification.drift);
+            }
+        }
+    }
+}